@@ -0,0 +1,88 @@
+//! The smallest program using the renderer: a spinning cube, orbited by dragging the mouse.
+
+use std::time::Duration;
+
+use antimodern::{
+    controller::{orbit::OrbitController, CameraController},
+    input::{Action, Axis, Input},
+    renderer, scene, time,
+};
+use anyhow::{Context, Result};
+use glam::{vec2, Quat, Vec3};
+use pollster::FutureExt;
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("cube")
+        .build(&event_loop)
+        .context("Failed to build window")?;
+
+    let adapter_options = renderer::adapter::AdapterOptions::from_env()?;
+    let instance = wgpu::Instance::new(adapter_options.backends);
+    let mut renderer = renderer::Renderer::new(&instance, &window, adapter_options).block_on()?;
+
+    let mut scene = scene::default();
+    let size = window.inner_size();
+    scene.camera.aspect_ratio = size.width as f32 / size.height as f32;
+
+    let mut cube = renderer::cube::PipelineState::new(
+        renderer.device(),
+        renderer.pipeline_cache(),
+        &renderer.target_config(),
+        renderer.view(),
+        &scene,
+    );
+
+    let mut input = Input::default();
+    let mut controller = OrbitController::new(&scene.camera, scene.cube.transform.position);
+    let mut time = time::Time::new(Duration::from_secs_f32(1. / 60.));
+
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent { event, .. } => {
+            if let Some((action, pressed)) = input.process_window_event(&event) {
+                controller.process_action(action, pressed);
+                if action == Action::ReleaseCursor {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(size) => {
+                    renderer.resize(size);
+                    if !renderer.minimized() {
+                        scene.camera.aspect_ratio = size.width as f32 / size.height as f32;
+                    }
+                }
+                _ => (),
+            }
+        }
+        Event::DeviceEvent { event, .. } => input.process_device_event(&event),
+        Event::MainEventsCleared => window.request_redraw(),
+        Event::RedrawRequested(..) => {
+            time.tick();
+            controller.process_mouse_motion(vec2(input.axis(Axis::LookX), input.axis(Axis::LookY)));
+            controller.process_scroll(input.axis(Axis::Zoom));
+            input.reset_axes();
+            controller.update(&mut scene.camera, time.delta());
+
+            // Frame rate dependent, the main binary shows how to animate at a fixed step.
+            scene.cube.transform.rotation *= Quat::from_axis_angle(Vec3::Y, 0.01);
+            cube.update(renderer.queue(), &scene);
+
+            let elapsed = time.elapsed().as_secs_f32();
+            if let Err(e) = renderer.render(&scene.camera, elapsed, &mut [&mut cube]) {
+                log::error!("{:?}", e);
+                *control_flow = ControlFlow::Exit;
+            }
+        }
+        _ => (),
+    });
+}
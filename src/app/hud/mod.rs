@@ -0,0 +1,128 @@
+//! The sample menu and the profiler HUD, drawn with the overlay.
+
+use glam::{vec2, vec4};
+
+use crate::{renderer, sample};
+
+/// Lists the samples with the selected one highlighted, in the top left corner.
+pub fn draw_menu(overlay: &mut renderer::overlay::PipelineState, current_sample: usize, dpi: f32) {
+    use renderer::overlay::PipelineState as Overlay;
+
+    let scale = 2. * dpi;
+    let margin = 8. * dpi;
+    let title = "Samples: Up, Down or 0-9 to select, Tab to close";
+    let lines: Vec<_> = sample::SAMPLES
+        .iter()
+        .enumerate()
+        .map(|(index, sample)| format!("{} {:<10} {}", index, sample.name, sample.description))
+        .collect();
+
+    let line_height = Overlay::text_size(" ", scale).y;
+    let width = lines
+        .iter()
+        .map(|line| Overlay::text_size(line, scale).x)
+        .fold(Overlay::text_size(title, scale).x, f32::max);
+    let height = line_height * (lines.len() + 1) as f32;
+    overlay.rect(
+        vec2(margin, margin),
+        vec2(width, height) + 2. * margin,
+        vec4(0., 0., 0., 0.75),
+    );
+
+    let origin = vec2(2. * margin, 2. * margin);
+    overlay.text(origin, scale, vec4(1., 1., 1., 1.), title);
+    for (index, line) in lines.iter().enumerate() {
+        let color = if index == current_sample {
+            vec4(1., 0.8, 0.2, 1.)
+        } else {
+            vec4(0.7, 0.7, 0.7, 1.)
+        };
+        let position = origin + vec2(0., line_height * (index + 1) as f32);
+        overlay.text(position, scale, color, line);
+    }
+}
+
+/// Shows the average timings of the profiled frames over a graph of their durations, in the top
+/// right corner.
+pub fn draw_hud(
+    overlay: &mut renderer::overlay::PipelineState,
+    summary: &renderer::profiler::Summary,
+    frame_times: &[f64],
+    gpu_supported: bool,
+    screen_width: f32,
+    dpi: f32,
+) {
+    use renderer::overlay::PipelineState as Overlay;
+
+    // Bars reach the top of the graph at 30 FPS.
+    const GRAPH_MAX: f64 = 1000. / 30.;
+
+    let scale = 2. * dpi;
+    let margin = 8. * dpi;
+    let scope_line = |scope: &renderer::profiler::ScopeAverage| {
+        let indent = 2 * (scope.depth as usize + 1);
+        format!(
+            "{:indent$}{:<width$}{:>7.2} ms",
+            "",
+            scope.name,
+            scope.duration,
+            indent = indent,
+            width = 20 - indent.min(20),
+        )
+    };
+    let mut lines = vec![format!(
+        "{:<20}{:>7.2} ms {:>4.0} fps",
+        "Frame",
+        summary.frame,
+        1000. / summary.frame.max(0.001)
+    )];
+    lines.push("CPU".to_owned());
+    lines.extend(summary.cpu.iter().map(scope_line));
+    if gpu_supported {
+        lines.push("GPU".to_owned());
+        lines.extend(summary.gpu.iter().map(scope_line));
+    } else {
+        lines.push("GPU timestamps not supported".to_owned());
+    }
+
+    let line_height = Overlay::text_size(" ", scale).y;
+    let width = lines
+        .iter()
+        .map(|line| Overlay::text_size(line, scale).x)
+        .fold(0., f32::max);
+    let graph_height = 4. * line_height;
+    let height = line_height * lines.len() as f32 + margin + graph_height;
+    let corner = vec2(screen_width - width - 3. * margin, margin);
+    overlay.rect(
+        corner,
+        vec2(width, height) + 2. * margin,
+        vec4(0., 0., 0., 0.75),
+    );
+
+    let origin = corner + margin;
+    for (index, line) in lines.iter().enumerate() {
+        let position = origin + vec2(0., line_height * index as f32);
+        overlay.text(position, scale, vec4(1., 1., 1., 1.), line);
+    }
+
+    // The most recent frames that fit, newest on the right.
+    let bar_width = dpi;
+    let bottom = origin.y + height;
+    let bars = frame_times.len().min((width / bar_width) as usize);
+    for (index, &frame_time) in frame_times[frame_times.len() - bars..].iter().enumerate() {
+        let bar_height = (frame_time / GRAPH_MAX).min(1.) as f32 * graph_height;
+        let color = if frame_time <= 1000. / 60. {
+            vec4(0.3, 0.9, 0.3, 1.)
+        } else if frame_time <= GRAPH_MAX {
+            vec4(1., 0.8, 0.2, 1.)
+        } else {
+            vec4(1., 0.3, 0.3, 1.)
+        };
+        let x = origin.x + width - (bars - index) as f32 * bar_width;
+        overlay.rect(
+            vec2(x, bottom - bar_height),
+            vec2(bar_width, bar_height),
+            color,
+        );
+    }
+}
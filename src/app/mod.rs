@@ -0,0 +1,549 @@
+//! The demo application: a window showing the samples, with the camera controllers, the menu,
+//! the profiler HUD and the actions bound in the input map.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use glam::vec2;
+use log::{debug, error, info, warn};
+use pollster::FutureExt;
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::{Fullscreen, Window, WindowBuilder},
+};
+
+use crate::{
+    controller::{self, CameraController},
+    entity,
+    input::{self, Action, Axis},
+    renderer::{self, adapter::AdapterOptions, Renderer},
+    sample::{self, Sample},
+    scene, time,
+};
+
+mod hud;
+
+/// Optional key bindings, see `input::InputMap::load`.
+const INPUT_CONFIG: &str = "input.cfg";
+
+/// Fixed simulation steps per second.
+const SIMULATION_RATE: f32 = 60.;
+
+const FRAME_RATE_CAPS: [Option<f32>; 4] = [None, Some(30.), Some(60.), Some(144.)];
+
+/// Where `Action::SaveTrace` saves the profiled frames without a trace path.
+const TRACE_PATH: &str = "trace.json";
+
+/// How the application starts, see the command line usage of the binary.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Loads the scene from this file instead of using the built in one.
+    pub scene: Option<PathBuf>,
+    /// Index of the first sample in `sample::SAMPLES`.
+    pub sample: usize,
+    /// Window size in logical pixels.
+    pub window_size: (u32, u32),
+    pub fullscreen: bool,
+    pub title: String,
+    /// Seed for the particle placement, overriding the scene's.
+    pub seed: Option<u64>,
+    /// Exits after presenting this many frames.
+    pub frames: Option<u64>,
+    /// Saves the frame `screenshot_frame`, counting from 1, to this path.
+    pub screenshot: Option<PathBuf>,
+    pub screenshot_frame: u64,
+    /// Profiles every frame and saves them as a Chrome trace on exit.
+    pub trace: Option<PathBuf>,
+    pub adapter: AdapterOptions,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            scene: None,
+            sample: 0,
+            window_size: (640, 360),
+            fullscreen: false,
+            title: "antimodern".to_owned(),
+            seed: None,
+            frames: None,
+            screenshot: None,
+            screenshot_frame: 1,
+            trace: None,
+            adapter: AdapterOptions::default(),
+        }
+    }
+}
+
+/// Opens the window and runs the samples until it is closed. Only returns if setting up fails.
+pub fn run(options: Options) -> Result<()> {
+    let event_loop = EventLoop::new();
+    let mut app = App::new(options, &event_loop)?;
+    event_loop.run(move |event, _, control_flow| app.handle_event(event, control_flow))
+}
+
+struct App {
+    options: Options,
+    instance: wgpu::Instance,
+    window: Window,
+    renderer: Renderer,
+    samples: Vec<Box<dyn Sample>>,
+    current_sample: usize,
+    input: input::Input,
+    orbiting: bool,
+    controller: Box<dyn CameraController>,
+    time: time::Time,
+    previous_scene: entity::Scene,
+    frame_limiter: time::FrameLimiter,
+    /// The perspective field of view to go back to after the orthographic projections.
+    perspective_fov: entity::Fov,
+    cursor_locked: bool,
+    menu_open: bool,
+    hud_open: bool,
+}
+
+impl App {
+    fn new(options: Options, event_loop: &EventLoop<()>) -> Result<Self> {
+        let (width, height) = options.window_size;
+        let window = WindowBuilder::new()
+            .with_title(&options.title)
+            .with_inner_size(winit::dpi::LogicalSize::<u32> { width, height })
+            .with_fullscreen(options.fullscreen.then(|| Fullscreen::Borderless(None)))
+            .build(event_loop)
+            .context("Failed to build window")?;
+
+        let instance = wgpu::Instance::new(options.adapter.backends);
+        let mut renderer = Renderer::new(&instance, &window, options.adapter.clone()).block_on()?;
+        renderer.profiler().set_enabled(options.trace.is_some());
+
+        let mut scene = match &options.scene {
+            Some(path) => scene::load(path)?,
+            None => scene::default(),
+        };
+        let inner_size = window.inner_size();
+        scene.camera.aspect_ratio = inner_size.width as f32 / inner_size.height as f32;
+        scene.camera.reverse_z = renderer.reverse_z();
+        if options.seed.is_some() {
+            scene.particle_system.seed = options.seed;
+        }
+
+        info!("{:#?}", &scene);
+
+        let mut samples: Vec<Box<dyn Sample>> = sample::SAMPLES
+            .iter()
+            .map(|info| (info.new)(scene))
+            .collect();
+        let current_sample = options.sample;
+        let start = Instant::now();
+        samples[current_sample].setup(&renderer);
+        info!(
+            "Set up {} sample in {:.2} ms",
+            sample::SAMPLES[current_sample].name,
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+
+        let input_map = if Path::new(INPUT_CONFIG).exists() {
+            input::InputMap::load(INPUT_CONFIG)?
+        } else {
+            input::InputMap::default()
+        };
+
+        Ok(Self {
+            options,
+            instance,
+            window,
+            renderer,
+            samples,
+            current_sample,
+            input: input::Input::new(input_map),
+            orbiting: false,
+            controller: make_controller(false, &scene),
+            time: time::Time::new(Duration::from_secs_f32(1. / SIMULATION_RATE)),
+            previous_scene: scene,
+            frame_limiter: time::FrameLimiter::new(None),
+            perspective_fov: entity::Fov::default(),
+            cursor_locked: false,
+            menu_open: false,
+            hud_open: false,
+        })
+    }
+
+    fn handle_event(&mut self, event: Event<()>, control_flow: &mut ControlFlow) {
+        debug!("{:#?}", event);
+
+        match event {
+            Event::WindowEvent { event, .. } => self.window_event(event, control_flow),
+            Event::DeviceEvent { event, .. } => self.input.process_device_event(&event),
+            Event::LoopDestroyed => {
+                if let Some(path) = &self.options.trace {
+                    save_trace(&mut self.renderer, path);
+                }
+            }
+            Event::MainEventsCleared => {
+                if *control_flow == ControlFlow::Exit {
+                    return;
+                }
+                // Sleeps until the window is restored instead of spinning on skipped frames.
+                if self.renderer.minimized() {
+                    *control_flow = ControlFlow::Wait;
+                    return;
+                }
+                if let Some(wake_up) = self.frame_limiter.sleep_until() {
+                    *control_flow = ControlFlow::WaitUntil(wake_up);
+                    return;
+                }
+                self.frame_limiter.wait();
+                *control_flow = ControlFlow::Poll;
+                self.window.request_redraw();
+            }
+            Event::RedrawRequested(..) => self.redraw(control_flow),
+            _ => (),
+        }
+    }
+
+    fn window_event(&mut self, event: WindowEvent, control_flow: &mut ControlFlow) {
+        let action = self.input.process_window_event(&event);
+        match event {
+            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(size) => self.resize(size),
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => self.resize(*new_inner_size),
+            _ => (),
+        }
+
+        if self.input.take_rebound() {
+            if let Err(e) = self.input.map().save(INPUT_CONFIG) {
+                error!("{:?}", e);
+            }
+        }
+
+        let (action, pressed) = match action {
+            Some(action) => action,
+            None => return,
+        };
+        // Mouse buttons only drive the camera while the cursor is grabbed, but releases always go
+        // through so that drags don't get stuck.
+        let mouse = matches!(action, Action::Rotate | Action::Pan);
+        if action == Action::Rotate && !pressed && !self.cursor_locked {
+            self.window.set_cursor_grab(true).unwrap();
+            self.window.set_cursor_visible(false);
+            self.cursor_locked = true;
+            return;
+        }
+        if (self.cursor_locked || !mouse || !pressed)
+            && self.controller.process_action(action, pressed)
+        {
+            return;
+        }
+        if self.samples[self.current_sample].input(action, pressed) || pressed {
+            return;
+        }
+
+        self.action(action);
+    }
+
+    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
+        self.renderer.resize(size);
+        if !self.renderer.minimized() {
+            for sample in &mut self.samples {
+                sample.scene_mut().camera.aspect_ratio = size.width as f32 / size.height as f32;
+            }
+        }
+    }
+
+    /// Handles the release of an action neither the controller nor the sample used.
+    fn action(&mut self, action: Action) {
+        let renderer = &mut self.renderer;
+        let scene = self.samples[self.current_sample].scene_mut();
+        let mut selected_sample = action.sample_index();
+        match action {
+            Action::Rebind => self.input.start_rebind(),
+            Action::TogglePause => {
+                self.time.set_paused(!self.time.paused());
+                info!(
+                    "Paused: {} at {:?}",
+                    self.time.paused(),
+                    self.time.elapsed()
+                );
+            }
+            Action::Step => self.time.step(),
+            Action::ReleaseCursor => {
+                self.window.set_cursor_grab(false).unwrap();
+                self.window.set_cursor_visible(true);
+                self.cursor_locked = false;
+            }
+            Action::CycleSampleCount => {
+                let supported = renderer.supported_sample_counts();
+                let next = supported
+                    .iter()
+                    .position(|&n| n == renderer.sample_count())
+                    .map_or(0, |i| (i + 1) % supported.len());
+                let sample_count = supported[next];
+                if let Err(e) = renderer.set_sample_count(sample_count) {
+                    error!("{:?}", e);
+                    return;
+                }
+                info!("MSAA sample count: {}", sample_count);
+            }
+            Action::ToggleReverseZ => {
+                renderer.set_reverse_z(!renderer.reverse_z());
+                for sample in &mut self.samples {
+                    sample.scene_mut().camera.reverse_z = renderer.reverse_z();
+                }
+                info!("Reverse Z: {}", renderer.reverse_z());
+            }
+            Action::CycleToneMapping => {
+                use renderer::tonemap::ToneMapping;
+                let next = ToneMapping::ALL
+                    .iter()
+                    .position(|&t| t == renderer.tone_mapping())
+                    .map_or(0, |i| (i + 1) % ToneMapping::ALL.len());
+                renderer.set_tone_mapping(ToneMapping::ALL[next]);
+                info!("Tone mapping: {:?}", renderer.tone_mapping());
+            }
+            Action::IncreaseExposure => {
+                renderer.set_exposure(renderer.exposure() + 0.5);
+                info!("Exposure: {} EV", renderer.exposure());
+            }
+            Action::DecreaseExposure => {
+                renderer.set_exposure(renderer.exposure() - 0.5);
+                info!("Exposure: {} EV", renderer.exposure());
+            }
+            Action::ToggleBloom => {
+                let mut settings = renderer.bloom_settings();
+                settings.enabled = !settings.enabled;
+                renderer.set_bloom_settings(settings);
+                info!("Bloom: {:?}", settings);
+            }
+            Action::CyclePresentMode => {
+                let modes = renderer::PRESENT_MODES;
+                let next = modes
+                    .iter()
+                    .position(|&m| m == renderer.present_mode())
+                    .map_or(0, |i| (i + 1) % modes.len());
+                renderer.set_present_mode(modes[next]);
+                info!("Present mode: {:?}", renderer.present_mode());
+            }
+            Action::CycleFrameRateCap => {
+                let next = FRAME_RATE_CAPS
+                    .iter()
+                    .position(|&cap| cap == self.frame_limiter.max_frame_rate())
+                    .map_or(0, |i| (i + 1) % FRAME_RATE_CAPS.len());
+                self.frame_limiter.set_max_frame_rate(FRAME_RATE_CAPS[next]);
+                info!("Frame rate cap: {:?}", FRAME_RATE_CAPS[next]);
+            }
+            Action::CycleAntiAliasing => {
+                use renderer::AntiAliasing;
+                let next = AntiAliasing::ALL
+                    .iter()
+                    .position(|&a| a == renderer.anti_aliasing())
+                    .map_or(0, |i| (i + 1) % AntiAliasing::ALL.len());
+                if let Err(e) = renderer.set_anti_aliasing(AntiAliasing::ALL[next]) {
+                    error!("{:?}", e);
+                    return;
+                }
+                info!("Anti-aliasing: {:?}", renderer.anti_aliasing());
+            }
+            Action::SwitchFovAxis => {
+                // Keeps the current view but fixes the other axis on resize.
+                let camera = &mut scene.camera;
+                let aspect_ratio = camera.aspect_ratio;
+                if let entity::Projection::Perspective(fov) = &mut camera.projection {
+                    *fov = match *fov {
+                        entity::Fov::Vertical(_) => entity::Fov::Horizontal(
+                            fov.horizontal_radians(aspect_ratio).to_degrees(),
+                        ),
+                        entity::Fov::Horizontal(_) => {
+                            entity::Fov::Vertical(fov.vertical_radians(aspect_ratio).to_degrees())
+                        }
+                    };
+                }
+                info!("Projection: {:?}", camera.projection);
+            }
+            Action::CycleProjection => {
+                // Cycles through the projections, matching the size of the cube on screen.
+                let camera = &mut scene.camera;
+                let distance = (scene.cube.transform.position - camera.transform.position).length();
+                camera.projection = match camera.projection {
+                    entity::Projection::Perspective(fov) => {
+                        self.perspective_fov = fov;
+                        let fovy = fov.vertical_radians(camera.aspect_ratio);
+                        entity::Projection::Orthographic {
+                            height: 2. * distance * (fovy * 0.5).tan(),
+                        }
+                    }
+                    entity::Projection::Orthographic { height } => {
+                        let half_height = height * 0.5;
+                        let half_width = half_height * camera.aspect_ratio;
+                        entity::Projection::OrthographicBounds {
+                            left: -half_width,
+                            right: half_width,
+                            bottom: -half_height,
+                            top: half_height,
+                        }
+                    }
+                    entity::Projection::OrthographicBounds { .. } => {
+                        entity::Projection::Perspective(self.perspective_fov)
+                    }
+                };
+                info!("Projection: {:?}", camera.projection);
+            }
+            Action::SwitchController => {
+                self.orbiting = !self.orbiting;
+                self.controller = make_controller(self.orbiting, scene);
+                info!("Orbit controller: {}", self.orbiting);
+            }
+            Action::Focus => {
+                let cube = &scene.cube.transform;
+                self.controller
+                    .focus(cube.position, cube.scale.length() * 0.5);
+            }
+            Action::NextSample => {
+                selected_sample = Some((self.current_sample + 1) % self.samples.len());
+            }
+            Action::PreviousSample => {
+                let count = self.samples.len();
+                selected_sample = Some((self.current_sample + count - 1) % count);
+            }
+            Action::ToggleMenu => self.menu_open = !self.menu_open,
+            Action::ToggleProfiler => {
+                self.hud_open = !self.hud_open;
+                renderer
+                    .profiler()
+                    .set_enabled(self.hud_open || self.options.trace.is_some());
+            }
+            Action::SaveTrace => {
+                let path = self.options.trace.as_deref();
+                save_trace(renderer, path.unwrap_or(Path::new(TRACE_PATH)));
+            }
+            _ => (),
+        }
+
+        match selected_sample {
+            Some(index) if index < self.samples.len() && index != self.current_sample => {
+                self.select_sample(index)
+            }
+            _ => (),
+        }
+    }
+
+    fn select_sample(&mut self, index: usize) {
+        self.samples[self.current_sample].teardown();
+        self.current_sample = index;
+        self.samples[index].setup(&self.renderer);
+        self.previous_scene = *self.samples[index].scene();
+        self.controller = make_controller(self.orbiting, &self.previous_scene);
+        info!("Sample: {}", sample::SAMPLES[index].name);
+    }
+
+    fn redraw(&mut self, control_flow: &mut ControlFlow) {
+        self.time.tick();
+        let renderer = &mut self.renderer;
+        let sample = &mut self.samples[self.current_sample];
+        let scene = sample.scene_mut();
+        if self.cursor_locked {
+            self.controller.process_mouse_motion(vec2(
+                self.input.axis(Axis::LookX),
+                self.input.axis(Axis::LookY),
+            ));
+            let scroll = self.input.axis(Axis::Zoom);
+            if scroll != 0. && !self.controller.process_scroll(scroll) {
+                let zoom = scroll * -2.;
+                match &mut scene.camera.projection {
+                    entity::Projection::Perspective(fov) => {
+                        *fov = fov.with_degrees((fov.degrees() + zoom).clamp(30., 120.));
+                    }
+                    entity::Projection::Orthographic { height } => {
+                        *height = (*height * (1. + zoom * 0.01)).clamp(0.1, 1000.);
+                    }
+                    entity::Projection::OrthographicBounds { .. } => (),
+                }
+                debug!("projection: {:?}", scene.camera.projection);
+            }
+        }
+        self.input.reset_axes();
+
+        self.controller.update(&mut scene.camera, self.time.delta());
+
+        scene.camera.jitter = renderer.jitter();
+        let update = Instant::now();
+        while self.time.next_step() {
+            self.previous_scene = *sample.scene();
+            sample.update(self.time.fixed_step.as_secs_f32());
+        }
+        renderer.profiler().record("update", update);
+        let frame_scene = sample
+            .scene()
+            .interpolate(&self.previous_scene, self.time.alpha());
+
+        let dpi = self.window.scale_factor() as f32;
+        if self.menu_open {
+            hud::draw_menu(renderer.overlay(), self.current_sample, dpi);
+        }
+        if self.hud_open {
+            let profiler = renderer.profiler();
+            let summary = profiler.summary();
+            let frame_times: Vec<_> = profiler.frames().map(|f| f.duration).collect();
+            let gpu_supported = profiler.gpu_supported();
+            hud::draw_hud(
+                renderer.overlay(),
+                &summary,
+                &frame_times,
+                gpu_supported,
+                self.window.inner_size().width as f32,
+                dpi,
+            );
+        }
+
+        if let Some(path) = &self.options.screenshot {
+            if renderer.frame_index() + 1 == self.options.screenshot_frame {
+                renderer.request_screenshot(path.clone());
+            }
+        }
+
+        let t = self.time.frame_time().as_secs_f32();
+        match sample.render(renderer, &frame_scene, t) {
+            Ok(()) if matches!(self.options.frames, Some(frames) if renderer.frame_index() >= frames) =>
+            {
+                info!("Presented {} frames, exiting", renderer.frame_index());
+                *control_flow = ControlFlow::Exit;
+            }
+            Ok(()) => (),
+            Err(e) if e.downcast_ref::<renderer::DeviceLost>().is_some() => {
+                warn!("{:?}", e);
+                if let Err(e) = renderer.recover(&self.instance).block_on() {
+                    error!("{:?}", e);
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                sample.setup(renderer);
+                info!("Recovered from device loss");
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                *control_flow = ControlFlow::Exit;
+            }
+        }
+    }
+}
+
+fn make_controller(orbiting: bool, scene: &entity::Scene) -> Box<dyn CameraController> {
+    if orbiting {
+        Box::new(controller::orbit::OrbitController::new(
+            &scene.camera,
+            scene.cube.transform.position,
+        ))
+    } else {
+        Box::new(controller::fly::FlyController::default())
+    }
+}
+
+fn save_trace(renderer: &mut Renderer, path: &Path) {
+    match renderer.profiler().save_trace(path) {
+        Ok(()) => info!("Saved trace to {:?}", path),
+        Err(e) => error!("{:?}", e),
+    }
+}
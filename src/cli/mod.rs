@@ -0,0 +1,198 @@
+use anyhow::{bail, Context, Result};
+
+use antimodern::{
+    app,
+    renderer::adapter::{self, AdapterSelector},
+    sample,
+};
+
+pub const USAGE: &str = "\
+Usage: antimodern [OPTIONS]
+
+Options:
+    --scene <PATH>           Load the scene from a file instead of the built in one
+    --sample <NAME|INDEX>    Start with this sample, see --list-samples [default: all]
+    --size <WIDTHxHEIGHT>    Window size in logical pixels [default: 640x360]
+    --fullscreen             Open a borderless fullscreen window
+    --title <TITLE>          Window title [default: antimodern]
+    --seed <SEED>            Seed for the particle placement, overriding the scene
+    --frames <N>             Exit after presenting N frames
+    --screenshot <PATH>      Save a frame as a PPM image
+    --screenshot-frame <N>   Which frame to save, counting from 1 [default: 1]
+    --trace <PATH>           Profile the frames and save them as a Chrome trace on exit
+    --log-level <FILTER>     Log filter like info or antimodern=debug [env: RUST_LOG]
+    --backend <BACKENDS>     Comma separated vulkan, metal, dx12, dx11, gl, or primary,
+                             secondary, all [env: WGPU_BACKEND]
+    --power <low|high>       Prefer low power or high performance adapters [env: WGPU_POWER_PREF]
+    --adapter <NAME|INDEX>   Use the adapter with this index or part of its name, see
+                             --list-adapters [env: WGPU_ADAPTER_NAME]
+    --fallback-adapter       Force the software fallback adapter
+    --list-adapters          Print the adapters of the backends with their features and exit
+    --list-samples           Print the samples and exit
+    -h, --help               Print this help and exit
+";
+
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub options: app::Options,
+    pub log_level: Option<String>,
+    pub list_adapters: bool,
+    pub list_samples: bool,
+    pub help: bool,
+}
+
+impl Args {
+    /// Parses arguments without the program name. The environment variables fill in the adapter
+    /// options no flag set, and aren't read at all for `--help` and `--list-samples`.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self {
+            options: app::Options::default(),
+            log_level: None,
+            list_adapters: false,
+            list_samples: false,
+            help: false,
+        };
+        let mut backends = None;
+        let mut power_preference = None;
+        let mut selector = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Accepts both `--flag value` and `--flag=value`.
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_owned(), Some(value.to_owned()))
+                }
+                _ => (arg, None),
+            };
+            let mut value = || -> Result<String> {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .with_context(|| format!("{} needs a value", flag))
+            };
+
+            let options = &mut parsed.options;
+            match flag.as_str() {
+                "--scene" => options.scene = Some(value()?.into()),
+                "--sample" => options.sample = parse_sample(&value()?)?,
+                "--size" => options.window_size = parse_size(&value()?)?,
+                "--fullscreen" => options.fullscreen = true,
+                "--title" => options.title = value()?,
+                "--seed" => options.seed = Some(parse_number(&flag, &value()?)?),
+                "--frames" => options.frames = Some(parse_number(&flag, &value()?)?),
+                "--screenshot" => options.screenshot = Some(value()?.into()),
+                "--screenshot-frame" => {
+                    options.screenshot_frame = parse_number(&flag, &value()?)?;
+                    if options.screenshot_frame == 0 {
+                        bail!("--screenshot-frame counts from 1");
+                    }
+                }
+                "--trace" => options.trace = Some(value()?.into()),
+                "--log-level" => parsed.log_level = Some(value()?),
+                "--backend" => backends = Some(adapter::parse_backends(&value()?)?),
+                "--power" => power_preference = Some(adapter::parse_power_preference(&value()?)?),
+                "--adapter" => selector = Some(AdapterSelector::parse(&value()?)),
+                "--fallback-adapter" => options.adapter.force_fallback_adapter = true,
+                "--list-adapters" => parsed.list_adapters = true,
+                "--list-samples" => parsed.list_samples = true,
+                "-h" | "--help" => parsed.help = true,
+                _ => bail!("Unknown argument {:?}\n\n{}", flag, USAGE),
+            }
+        }
+
+        if !parsed.help && !parsed.list_samples {
+            if backends.is_none() {
+                backends = adapter::backends_from_env()?;
+            }
+            power_preference = power_preference.or_else(wgpu::util::power_preference_from_env);
+            selector = selector.or_else(adapter::selector_from_env);
+        }
+        let adapter = &mut parsed.options.adapter;
+        adapter.backends = backends.unwrap_or(adapter.backends);
+        adapter.power_preference = power_preference.unwrap_or(adapter.power_preference);
+        adapter.selector = selector;
+
+        Ok(parsed)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .ok()
+        .with_context(|| format!("{} expects a number, got {:?}", flag, value))
+}
+
+fn parse_sample(name: &str) -> Result<usize> {
+    sample::find(name).with_context(|| {
+        let names: Vec<_> = sample::SAMPLES.iter().map(|s| s.name).collect();
+        format!("Unknown sample {:?}, expected one of {:?}", name, names)
+    })
+}
+
+fn parse_size(size: &str) -> Result<(u32, u32)> {
+    let (width, height) = size
+        .split_once('x')
+        .with_context(|| format!("Expected a size like 1280x720, got {:?}", size))?;
+    let width = parse_number("--size", width)?;
+    let height = parse_number("--size", height)?;
+    if width == 0 || height == 0 {
+        bail!("The window size can't be zero");
+    }
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args> {
+        Args::parse(args.iter().map(|&arg| arg.to_owned()))
+    }
+
+    fn error(args: &[&str]) -> String {
+        format!("{:#}", parse(args).unwrap_err())
+    }
+
+    #[test]
+    fn values_follow_the_flag_or_an_equals_sign() {
+        let args = parse(&["--title", "a=b", "--size=800x600", "--frames", "3"]).unwrap();
+        assert_eq!(args.options.title, "a=b");
+        assert_eq!(args.options.window_size, (800, 600));
+        assert_eq!(args.options.frames, Some(3));
+    }
+
+    #[test]
+    fn missing_values_are_errors() {
+        assert_eq!(error(&["--title"]), "--title needs a value");
+        assert_eq!(
+            error(&["--fullscreen", "--frames"]),
+            "--frames needs a value"
+        );
+    }
+
+    #[test]
+    fn sizes_are_positive() {
+        assert_eq!(parse_size("1280x720").unwrap(), (1280, 720));
+        assert!(parse_size("1280").is_err());
+        assert!(parse_size("1280xabc").is_err());
+        assert_eq!(error(&["--size", "0x10"]), "The window size can't be zero");
+    }
+
+    #[test]
+    fn screenshot_frames_count_from_one() {
+        let args = parse(&["--screenshot-frame=1"]).unwrap();
+        assert_eq!(args.options.screenshot_frame, 1);
+        assert_eq!(
+            error(&["--screenshot-frame", "0"]),
+            "--screenshot-frame counts from 1"
+        );
+    }
+
+    #[test]
+    fn unknown_flags_are_errors() {
+        assert!(error(&["--bogus"]).starts_with("Unknown argument \"--bogus\""));
+        assert!(error(&["--bogus=1"]).starts_with("Unknown argument \"--bogus\""));
+    }
+}
@@ -0,0 +1,216 @@
+use std::{f32::consts::PI, time::Duration};
+
+use glam::{vec3, EulerRot, Quat, Vec2, Vec3};
+
+use crate::{
+    controller::{self, CameraController},
+    entity,
+    input::Action,
+};
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Movement {
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+    up: bool,
+    down: bool,
+    sprint: bool,
+}
+
+/// First person camera: the move actions (WASD, Q and E by default) to move, sprint to go faster
+/// and the mouse to look around.
+#[derive(Debug, Copy, Clone)]
+pub struct FlyController {
+    /// Units per second.
+    pub speed: f32,
+    pub sprint_multiplier: f32,
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// Seconds the velocity takes to get about two thirds of the way to the target velocity.
+    /// Zero disables smoothing.
+    pub smoothing: f32,
+    movement: Movement,
+    rotation_delta: Vec2,
+    velocity: Vec3,
+    focus: Option<Vec3>,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self {
+            speed: 5.,
+            sprint_multiplier: 4.,
+            sensitivity: 0.001,
+            smoothing: 0.1,
+            movement: Movement::default(),
+            rotation_delta: Vec2::ZERO,
+            velocity: Vec3::ZERO,
+            focus: None,
+        }
+    }
+}
+
+impl FlyController {
+    fn target_velocity(&self, rotation: Quat) -> Vec3 {
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let Movement {
+            forward,
+            backward,
+            left,
+            right,
+            up,
+            down,
+            sprint,
+        } = self.movement;
+
+        let direction = rotation * vec3(axis(right, left), 0., axis(forward, backward))
+            + Vec3::Y * axis(up, down);
+        let speed = if sprint {
+            self.speed * self.sprint_multiplier
+        } else {
+            self.speed
+        };
+        direction.normalize_or_zero() * speed
+    }
+}
+
+impl CameraController for FlyController {
+    fn process_action(&mut self, action: Action, pressed: bool) -> bool {
+        let movement = &mut self.movement;
+        match action {
+            Action::MoveForward => movement.forward = pressed,
+            Action::MoveBackward => movement.backward = pressed,
+            Action::MoveLeft => movement.left = pressed,
+            Action::MoveRight => movement.right = pressed,
+            Action::MoveUp => movement.up = pressed,
+            Action::MoveDown => movement.down = pressed,
+            Action::Sprint => movement.sprint = pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    fn process_mouse_motion(&mut self, delta: Vec2) {
+        self.rotation_delta += delta;
+    }
+
+    fn focus(&mut self, target: Vec3, _radius: f32) {
+        self.focus = Some(target);
+    }
+
+    fn update(&mut self, camera: &mut entity::Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+
+        if let Some(target) = self.focus.take() {
+            let (yaw, pitch) = controller::yaw_pitch(target - camera.transform.position);
+            camera.transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.);
+        }
+
+        let (yaw, pitch, roll) = camera.transform.rotation.to_euler(EulerRot::YXZ);
+        let rotation = self.rotation_delta * self.sensitivity;
+        let pitch = (pitch + rotation.y).clamp(PI * -0.5, PI * 0.5);
+        camera.transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw + rotation.x, pitch, roll);
+        self.rotation_delta = Vec2::ZERO;
+
+        // Exponential smoothing, with the velocity integrated exactly over the frame so that
+        // neither it nor the position depend on the frame rate.
+        let target_velocity = self.target_velocity(camera.transform.rotation);
+        if self.smoothing > 0. {
+            let decay = (-dt / self.smoothing).exp();
+            let difference = self.velocity - target_velocity;
+            camera.transform.position +=
+                target_velocity * dt + difference * self.smoothing * (1. - decay);
+            self.velocity = target_velocity + difference * decay;
+        } else {
+            self.velocity = target_velocity;
+            camera.transform.position += self.velocity * dt;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::vec3;
+
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-4),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    fn fly(controller: &mut FlyController, camera: &mut entity::Camera, steps: u32, dt: f32) {
+        for _ in 0..steps {
+            controller.update(camera, Duration::from_secs_f32(dt));
+        }
+    }
+
+    #[test]
+    fn position_does_not_depend_on_the_frame_rate() {
+        for smoothing in [0., 0.1, 0.5] {
+            for actions in [
+                &[Action::MoveForward][..],
+                &[Action::MoveRight, Action::MoveUp],
+                &[Action::MoveBackward, Action::Sprint],
+            ] {
+                let mut one = FlyController {
+                    smoothing,
+                    ..Default::default()
+                };
+                for &action in actions {
+                    one.process_action(action, true);
+                }
+                let mut two = one;
+                let mut one_camera = entity::Camera::default();
+                let mut two_camera = entity::Camera::default();
+
+                fly(&mut one, &mut one_camera, 1, 0.1);
+                fly(&mut two, &mut two_camera, 2, 0.05);
+                assert_close(two_camera.transform.position, one_camera.transform.position);
+                assert_close(two.velocity, one.velocity);
+
+                fly(&mut one, &mut one_camera, 3, 0.2);
+                fly(&mut two, &mut two_camera, 12, 0.05);
+                assert_close(two_camera.transform.position, one_camera.transform.position);
+            }
+        }
+    }
+
+    #[test]
+    fn smoothing_converges() {
+        let mut controller = FlyController::default();
+        let mut camera = entity::Camera::default();
+        controller.process_action(Action::MoveForward, true);
+
+        fly(&mut controller, &mut camera, 1, 0.01);
+        assert!(controller.velocity.z > 0. && controller.velocity.z < controller.speed);
+
+        fly(&mut controller, &mut camera, 100, 0.02);
+        assert_close(controller.velocity, vec3(0., 0., controller.speed));
+        let start = camera.transform.position;
+        fly(&mut controller, &mut camera, 1, 0.5);
+        assert_close(
+            camera.transform.position - start,
+            vec3(0., 0., controller.speed * 0.5),
+        );
+
+        controller.process_action(Action::MoveForward, false);
+        fly(&mut controller, &mut camera, 100, 0.02);
+        assert_close(controller.velocity, Vec3::ZERO);
+    }
+
+    #[test]
+    fn focus_turns_towards_the_target() {
+        let mut controller = FlyController::default();
+        let mut camera = entity::Camera::default();
+        controller.focus(vec3(3., 4., 0.), 1.);
+        fly(&mut controller, &mut camera, 1, 0.01);
+        assert_close(camera.transform.rotation * Vec3::Z, vec3(0.6, 0.8, 0.));
+    }
+}
@@ -0,0 +1,40 @@
+//! Camera controllers turning input actions into camera movement.
+
+use std::time::Duration;
+
+use glam::{Vec2, Vec3};
+
+use crate::{entity, input::Action};
+
+pub mod fly;
+pub mod orbit;
+
+/// Moves a camera from user input. Input is collected through the `process_*` methods as events
+/// arrive and applied once per frame in `update`.
+pub trait CameraController {
+    /// Returns whether the controller uses the action.
+    fn process_action(&mut self, action: Action, pressed: bool) -> bool;
+
+    /// Mouse movement in pixels.
+    fn process_mouse_motion(&mut self, delta: Vec2);
+
+    /// Scrolling in lines, positive away from the user. Returns whether the controller uses it.
+    fn process_scroll(&mut self, _delta: f32) -> bool {
+        false
+    }
+
+    /// Turns towards a sphere around `target`, moving closer if the controller can.
+    fn focus(&mut self, target: Vec3, radius: f32);
+
+    fn update(&mut self, camera: &mut entity::Camera, dt: Duration);
+}
+
+/// Yaw and pitch of a `EulerRot::YXZ` rotation looking along `direction`, matching the +Z forward
+/// of `entity::Camera`.
+fn yaw_pitch(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize_or_zero();
+    (
+        direction.x.atan2(direction.z),
+        (-direction.y).clamp(-1., 1.).asin(),
+    )
+}
@@ -0,0 +1,217 @@
+use std::{f32::consts::PI, time::Duration};
+
+use glam::{EulerRot, Quat, Vec2, Vec3};
+
+use crate::{
+    controller::{self, CameraController},
+    entity,
+    input::Action,
+};
+
+/// Orbits around a target point: drag while rotating (the left mouse button by default) or panning
+/// (the right or middle button), and scroll to move closer.
+#[derive(Debug, Copy, Clone)]
+pub struct OrbitController {
+    /// Radians per pixel of mouse movement.
+    pub sensitivity: f32,
+    /// Fraction of the distance to the target panned per pixel of mouse movement.
+    pub pan_sensitivity: f32,
+    /// Fraction of the distance to the target moved per line scrolled.
+    pub dolly_sensitivity: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Seconds the target and distance take to get about two thirds of the way to where they are
+    /// going. Zero disables smoothing.
+    pub smoothing: f32,
+    yaw: f32,
+    pitch: f32,
+    target: Vec3,
+    distance: f32,
+    goal_target: Vec3,
+    goal_distance: f32,
+    rotating: bool,
+    panning: bool,
+}
+
+impl OrbitController {
+    /// Starts orbiting `target` from where the camera currently is.
+    pub fn new(camera: &entity::Camera, target: Vec3) -> Self {
+        let offset = target - camera.transform.position;
+        let (yaw, pitch) = controller::yaw_pitch(offset);
+        let min_distance = 0.1;
+        let distance = offset.length().max(min_distance);
+
+        Self {
+            sensitivity: 0.005,
+            pan_sensitivity: 0.001,
+            dolly_sensitivity: 0.1,
+            min_distance,
+            max_distance: 1000.,
+            smoothing: 0.1,
+            yaw,
+            pitch,
+            target,
+            distance,
+            goal_target: target,
+            goal_distance: distance,
+            rotating: false,
+            panning: false,
+        }
+    }
+
+    fn rotation(&self) -> Quat {
+        Quat::from_euler(EulerRot::YXZ, self.yaw, self.pitch, 0.)
+    }
+}
+
+impl CameraController for OrbitController {
+    fn process_action(&mut self, action: Action, pressed: bool) -> bool {
+        match action {
+            Action::Rotate => self.rotating = pressed,
+            Action::Pan => self.panning = pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    fn process_mouse_motion(&mut self, delta: Vec2) {
+        if self.rotating {
+            self.yaw += delta.x * self.sensitivity;
+            // Stops just short of the poles, where yaw would flip.
+            let limit = PI * 0.5 - 0.001;
+            self.pitch = (self.pitch + delta.y * self.sensitivity).clamp(-limit, limit);
+        } else if self.panning {
+            let rotation = self.rotation();
+            let pan = rotation * Vec3::X * -delta.x + rotation * Vec3::Y * delta.y;
+            self.goal_target += pan * self.goal_distance * self.pan_sensitivity;
+        }
+    }
+
+    fn process_scroll(&mut self, delta: f32) -> bool {
+        let scale = (1. - delta * self.dolly_sensitivity).max(0.1);
+        self.goal_distance =
+            (self.goal_distance * scale).clamp(self.min_distance, self.max_distance);
+        true
+    }
+
+    fn focus(&mut self, target: Vec3, radius: f32) {
+        self.goal_target = target;
+        self.goal_distance = (radius * 3.).clamp(self.min_distance, self.max_distance);
+    }
+
+    fn update(&mut self, camera: &mut entity::Camera, dt: Duration) {
+        let blend = if self.smoothing > 0. {
+            1. - (-dt.as_secs_f32() / self.smoothing).exp()
+        } else {
+            1.
+        };
+        self.target = self.target.lerp(self.goal_target, blend);
+        self.distance += (self.goal_distance - self.distance) * blend;
+
+        let rotation = self.rotation();
+        camera.transform.rotation = rotation;
+        camera.transform.position = self.target - rotation * Vec3::Z * self.distance;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec2, vec3};
+
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-3),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    /// A camera 5 units behind the origin, orbiting it.
+    fn orbit() -> (OrbitController, entity::Camera) {
+        let camera = entity::Camera {
+            transform: entity::Transform {
+                position: vec3(0., 0., -5.),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        (OrbitController::new(&camera, Vec3::ZERO), camera)
+    }
+
+    fn settle(controller: &mut OrbitController, camera: &mut entity::Camera) {
+        for _ in 0..200 {
+            controller.update(camera, Duration::from_secs_f32(0.02));
+        }
+    }
+
+    #[test]
+    fn rotation_keeps_the_distance() {
+        let (mut controller, mut camera) = orbit();
+        controller.process_action(Action::Rotate, true);
+        for delta in [vec2(100., 0.), vec2(-30., 250.), vec2(400., -1000.)] {
+            controller.process_mouse_motion(delta);
+            controller.update(&mut camera, Duration::from_secs_f32(0.016));
+
+            let position = camera.transform.position;
+            assert!((position.length() - 5.).abs() < 1e-4, "{}", position);
+            assert_close(camera.transform.rotation * Vec3::Z, -position.normalize());
+        }
+    }
+
+    #[test]
+    fn pan_moves_the_target_sideways() {
+        let (mut controller, mut camera) = orbit();
+        controller.smoothing = 0.;
+        controller.process_action(Action::Pan, true);
+        controller.process_mouse_motion(vec2(100., 50.));
+        controller.update(&mut camera, Duration::from_secs_f32(0.016));
+
+        // Dragging right and down moves the target left and up, by a fraction of the distance.
+        let target = vec3(-100., 50., 0.) * 5. * controller.pan_sensitivity;
+        assert_close(controller.target, target);
+        assert_close(camera.transform.position, target + vec3(0., 0., -5.));
+    }
+
+    #[test]
+    fn dolly_approaches_and_clamps() {
+        let (mut controller, mut camera) = orbit();
+        assert!(controller.process_scroll(1.));
+        settle(&mut controller, &mut camera);
+        assert_close(camera.transform.position, vec3(0., 0., -4.5));
+
+        for _ in 0..100 {
+            controller.process_scroll(5.);
+        }
+        settle(&mut controller, &mut camera);
+        assert_close(
+            camera.transform.position,
+            vec3(0., 0., -controller.min_distance),
+        );
+    }
+
+    #[test]
+    fn focus_frames_the_target() {
+        let (mut controller, mut camera) = orbit();
+        controller.focus(vec3(1., 2., 3.), 0.5);
+        settle(&mut controller, &mut camera);
+        assert_close(controller.target, vec3(1., 2., 3.));
+        assert_close(camera.transform.position, vec3(1., 2., 1.5));
+    }
+
+    #[test]
+    fn smoothing_does_not_depend_on_the_frame_rate() {
+        let (mut one, mut one_camera) = orbit();
+        one.focus(vec3(1., 2., 3.), 2.);
+        let mut two = one;
+        let mut two_camera = one_camera;
+
+        one.update(&mut one_camera, Duration::from_secs_f32(0.1));
+        for _ in 0..2 {
+            two.update(&mut two_camera, Duration::from_secs_f32(0.05));
+        }
+        assert_close(two_camera.transform.position, one_camera.transform.position);
+    }
+}
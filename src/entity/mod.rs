@@ -1,4 +1,6 @@
-use glam::{Quat, Vec3};
+//! Plain data describing what is rendered: the camera, the cube and the particle system.
+
+use glam::{Mat4, Quat, Vec2, Vec3};
 
 #[derive(Debug, Copy, Clone, Default)]
 pub struct Scene {
@@ -7,13 +9,168 @@ pub struct Scene {
     pub particle_system: ParticleSystem,
 }
 
+impl Scene {
+    /// Blends the simulated entities between the `previous` and this fixed step. The camera is
+    /// updated every frame, so it is taken as is.
+    pub fn interpolate(&self, previous: &Scene, alpha: f32) -> Scene {
+        let mut scene = *self;
+        scene.cube.transform = previous.cube.transform.lerp(&self.cube.transform, alpha);
+        scene.particle_system.transform = previous
+            .particle_system
+            .transform
+            .lerp(&self.particle_system.transform, alpha);
+        scene
+    }
+}
+
+/// Field of view in degrees, along the axis it is fixed to when the aspect ratio changes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Fov {
+    Vertical(f32),
+    Horizontal(f32),
+}
+
+impl Fov {
+    pub fn degrees(self) -> f32 {
+        match self {
+            Fov::Vertical(degrees) | Fov::Horizontal(degrees) => degrees,
+        }
+    }
+
+    /// The same kind of field of view with a different angle.
+    pub fn with_degrees(self, degrees: f32) -> Self {
+        match self {
+            Fov::Vertical(_) => Fov::Vertical(degrees),
+            Fov::Horizontal(_) => Fov::Horizontal(degrees),
+        }
+    }
+
+    /// Vertical field of view in radians for a width / height aspect ratio.
+    pub fn vertical_radians(self, aspect_ratio: f32) -> f32 {
+        match self {
+            Fov::Vertical(degrees) => degrees.to_radians(),
+            Fov::Horizontal(degrees) => {
+                2. * ((degrees.to_radians() * 0.5).tan() / aspect_ratio).atan()
+            }
+        }
+    }
+
+    /// Horizontal field of view in radians for a width / height aspect ratio.
+    pub fn horizontal_radians(self, aspect_ratio: f32) -> f32 {
+        match self {
+            Fov::Vertical(degrees) => {
+                2. * ((degrees.to_radians() * 0.5).tan() * aspect_ratio).atan()
+            }
+            Fov::Horizontal(degrees) => degrees.to_radians(),
+        }
+    }
+}
+
+impl Default for Fov {
+    fn default() -> Self {
+        Fov::Vertical(60.)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    Perspective(Fov),
+    /// Height of the view volume in world units, the width follows the aspect ratio.
+    Orthographic {
+        height: f32,
+    },
+    /// View volume bounds in view space, ignoring the aspect ratio.
+    OrthographicBounds {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+    },
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective(Fov::default())
+    }
+}
+
 #[derive(Debug, Copy, Clone, Default)]
 pub struct Camera {
     pub transform: Transform,
-    pub fov: f32,
+    pub projection: Projection,
+    /// Width / height of the viewport.
     pub aspect_ratio: f32,
     pub near: f32,
+    /// Ignored by perspective projections with `reverse_z`, which have no far plane.
     pub far: f32,
+    /// Maps the near plane to depth 1 and the far plane to 0, see `TargetConfig::reverse_z`.
+    pub reverse_z: bool,
+    /// Sub-pixel offset in NDC applied after projection, used by temporal anti-aliasing.
+    pub jitter: Vec2,
+}
+
+impl Camera {
+    pub fn view_matrix(&self) -> Mat4 {
+        let center = self.transform.position + self.transform.rotation * Vec3::Z;
+        let up = Vec3::Y;
+        Mat4::look_at_lh(self.transform.position, center, up)
+    }
+
+    pub fn projection_matrix(&self) -> Mat4 {
+        // Orthographic projections are linear in depth, so swapping the planes reverses them.
+        let (near, far) = if self.reverse_z {
+            (self.far, self.near)
+        } else {
+            (self.near, self.far)
+        };
+        let projection = match self.projection {
+            Projection::Perspective(fov) => {
+                let fovy = fov.vertical_radians(self.aspect_ratio);
+                if self.reverse_z {
+                    Mat4::perspective_infinite_reverse_lh(fovy, self.aspect_ratio, self.near)
+                } else {
+                    Mat4::perspective_lh(fovy, self.aspect_ratio, self.near, self.far)
+                }
+            }
+            Projection::Orthographic { height } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                Mat4::orthographic_lh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+            Projection::OrthographicBounds {
+                left,
+                right,
+                bottom,
+                top,
+            } => Mat4::orthographic_lh(left, right, bottom, top, near, far),
+        };
+        Mat4::from_translation(self.jitter.extend(0.)) * projection
+    }
+
+    pub fn view_projection(&self) -> ViewProjection {
+        let view = self.view_matrix();
+        let projection = self.projection_matrix();
+        ViewProjection {
+            view,
+            projection,
+            view_projection: projection * view,
+        }
+    }
+}
+
+/// Camera matrices computed once per frame and shared by all pipelines.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ViewProjection {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
 }
 
 #[derive(Debug, Copy, Clone, Default)]
@@ -26,6 +183,8 @@ pub struct ParticleSystem {
     pub transform: Transform,
     pub max_count: u32,
     pub particle_size: f32,
+    /// Seeds the particle placement, `None` uses the current time.
+    pub seed: Option<u64>,
     pub lifetime: u32,
     pub min_speed: f32,
     pub max_speed: f32,
@@ -37,3 +196,140 @@ pub struct Transform {
     pub rotation: Quat,
     pub scale: Vec3,
 }
+
+impl Transform {
+    pub fn lerp(&self, other: &Transform, t: f32) -> Transform {
+        Transform {
+            position: self.position.lerp(other.position, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{vec2, vec3, Vec4};
+
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= 1e-4 * expected.abs().max(1.),
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    fn camera(projection: Projection, reverse_z: bool) -> Camera {
+        Camera {
+            projection,
+            aspect_ratio: 16. / 9.,
+            near: 0.1,
+            far: 100.,
+            reverse_z,
+            ..Default::default()
+        }
+    }
+
+    /// NDC of a view space point.
+    fn project(camera: &Camera, point: Vec3) -> Vec3 {
+        let clip = camera.projection_matrix() * point.extend(1.);
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn fov_converts_between_axes() {
+        for aspect_ratio in [0.5, 1., 4. / 3., 16. / 9., 3.] {
+            let vertical = Fov::Vertical(60.);
+            let horizontal = vertical.horizontal_radians(aspect_ratio);
+            assert_close(
+                (horizontal * 0.5).tan(),
+                (60f32.to_radians() * 0.5).tan() * aspect_ratio,
+            );
+
+            let back = Fov::Horizontal(horizontal.to_degrees()).vertical_radians(aspect_ratio);
+            assert_close(back, 60f32.to_radians());
+            assert_close(vertical.vertical_radians(aspect_ratio), 60f32.to_radians());
+            assert_close(
+                Fov::Horizontal(90.).horizontal_radians(aspect_ratio),
+                90f32.to_radians(),
+            );
+        }
+        assert_close(
+            Fov::Vertical(75.).horizontal_radians(1.),
+            Fov::Vertical(75.).vertical_radians(1.),
+        );
+    }
+
+    #[test]
+    fn perspective_depth_range() {
+        let camera = camera(Projection::default(), false);
+        assert_close(project(&camera, vec3(0., 0., camera.near)).z, 0.);
+        assert_close(project(&camera, vec3(0., 0., camera.far)).z, 1.);
+    }
+
+    #[test]
+    fn reverse_z_maps_near_to_one_and_infinity_to_zero() {
+        let camera = camera(Projection::default(), true);
+        assert_close(project(&camera, vec3(0., 0., camera.near)).z, 1.);
+        let far = project(&camera, vec3(0., 0., 1e7)).z;
+        assert!((0. ..1e-6).contains(&far), "{}", far);
+        // Beyond the far plane, which reverse-Z perspectives ignore.
+        assert!(project(&camera, vec3(0., 0., camera.far * 10.)).z > 0.);
+    }
+
+    #[test]
+    fn orthographic_bounds() {
+        let projection = Projection::OrthographicBounds {
+            left: -2.,
+            right: 4.,
+            bottom: -1.,
+            top: 3.,
+        };
+        for reverse_z in [false, true] {
+            let camera = camera(projection, reverse_z);
+            let (near_depth, far_depth) = if reverse_z { (1., 0.) } else { (0., 1.) };
+
+            let min = project(&camera, vec3(-2., -1., camera.near));
+            let max = project(&camera, vec3(4., 3., camera.far));
+            assert_close(min.x, -1.);
+            assert_close(min.y, -1.);
+            assert_close(min.z, near_depth);
+            assert_close(max.x, 1.);
+            assert_close(max.y, 1.);
+            assert_close(max.z, far_depth);
+        }
+    }
+
+    #[test]
+    fn orthographic_width_follows_aspect_ratio() {
+        let camera = camera(Projection::Orthographic { height: 2. }, false);
+        let corner = project(&camera, vec3(camera.aspect_ratio, 1., 1.));
+        assert_close(corner.x, 1.);
+        assert_close(corner.y, 1.);
+    }
+
+    #[test]
+    fn jitter_only_shifts_clip_space_xy() {
+        for projection in [
+            Projection::default(),
+            Projection::Orthographic { height: 5. },
+        ] {
+            let still = camera(projection, true);
+            let jittered = Camera {
+                jitter: vec2(0.25, -0.5),
+                ..still
+            };
+            let point = Vec4::new(1., -2., 5., 1.);
+            let a = still.projection_matrix() * point;
+            let b = jittered.projection_matrix() * point;
+
+            assert_close(b.z, a.z);
+            assert_close(b.w, a.w);
+            assert_close(b.x / b.w - a.x / a.w, 0.25);
+            assert_close(b.y / b.w - a.y / a.w, -0.5);
+        }
+    }
+}
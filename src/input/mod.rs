@@ -0,0 +1,670 @@
+//! Maps winit events to named actions and axes through rebindable, loadable key bindings.
+
+use std::{collections::HashSet, fmt::Write, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use winit::event::{
+    DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+
+/// Scroll distance of one line for touchpads and other devices reporting pixels.
+const PIXELS_PER_LINE: f32 = 20.;
+
+macro_rules! named {
+    ($(#[$meta:meta])* pub enum $name:ident { $($(#[$variant_meta:meta])* $variant:ident = $string:literal,)* }) => {
+        $(#[$meta])*
+        pub enum $name {
+            $($(#[$variant_meta])* $variant,)*
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($name::$variant => $string,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                Self::ALL.iter().copied().find(|value| value.name() == name)
+            }
+        }
+    };
+}
+
+named! {
+    #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+    pub enum Action {
+        MoveForward = "move_forward",
+        MoveBackward = "move_backward",
+        MoveLeft = "move_left",
+        MoveRight = "move_right",
+        MoveUp = "move_up",
+        MoveDown = "move_down",
+        Sprint = "sprint",
+        /// Also grabs the cursor while it is released.
+        Rotate = "rotate",
+        Pan = "pan",
+        ReleaseCursor = "release_cursor",
+        SwitchController = "switch_controller",
+        Focus = "focus",
+        SwitchFovAxis = "switch_fov_axis",
+        CycleProjection = "cycle_projection",
+        CycleSampleCount = "cycle_sample_count",
+        CycleAntiAliasing = "cycle_anti_aliasing",
+        ToggleReverseZ = "toggle_reverse_z",
+        CycleToneMapping = "cycle_tone_mapping",
+        IncreaseExposure = "increase_exposure",
+        DecreaseExposure = "decrease_exposure",
+        ToggleBloom = "toggle_bloom",
+        CyclePresentMode = "cycle_present_mode",
+        CycleFrameRateCap = "cycle_frame_rate_cap",
+        /// Selects the sample with the index in the name, see `sample::SAMPLES`.
+        SelectSample0 = "select_sample_0",
+        SelectSample1 = "select_sample_1",
+        SelectSample2 = "select_sample_2",
+        SelectSample3 = "select_sample_3",
+        SelectSample4 = "select_sample_4",
+        SelectSample5 = "select_sample_5",
+        SelectSample6 = "select_sample_6",
+        SelectSample7 = "select_sample_7",
+        SelectSample8 = "select_sample_8",
+        SelectSample9 = "select_sample_9",
+        NextSample = "next_sample",
+        PreviousSample = "previous_sample",
+        /// Shows the list of samples.
+        ToggleMenu = "toggle_menu",
+        /// Profiles the frames and shows their timings.
+        ToggleProfiler = "toggle_profiler",
+        /// Saves the profiled frames as a Chrome trace.
+        SaveTrace = "save_trace",
+        TogglePause = "toggle_pause",
+        /// Advances the simulation by one fixed step while paused.
+        Step = "step",
+        /// Rebinds the action of the next binding released to the binding released after it.
+        /// Releasing a binding of this action again in between cancels.
+        Rebind = "rebind",
+    }
+}
+
+impl Action {
+    /// The index of the sample a `SelectSample*` action selects.
+    pub fn sample_index(self) -> Option<usize> {
+        const SELECT_SAMPLE: [Action; 10] = [
+            Action::SelectSample0,
+            Action::SelectSample1,
+            Action::SelectSample2,
+            Action::SelectSample3,
+            Action::SelectSample4,
+            Action::SelectSample5,
+            Action::SelectSample6,
+            Action::SelectSample7,
+            Action::SelectSample8,
+            Action::SelectSample9,
+        ];
+        SELECT_SAMPLE.iter().position(|&action| action == self)
+    }
+}
+
+named! {
+    /// Continuous inputs, accumulated over a frame.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Axis {
+        /// Mouse movement in pixels by default.
+        LookX = "look_x",
+        LookY = "look_y",
+        /// Scrolling in lines by default, positive away from the user.
+        Zoom = "zoom",
+    }
+}
+
+named! {
+    /// Device input an axis is driven by.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum AxisSource {
+        MouseX = "MouseX",
+        MouseY = "MouseY",
+        Wheel = "Wheel",
+    }
+}
+
+/// An axis reads its source multiplied by `scale`, which inverts the axis when negative.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AxisBinding {
+    pub source: AxisSource,
+    pub scale: f32,
+}
+
+impl AxisBinding {
+    pub fn new(source: AxisSource) -> Self {
+        Self { source, scale: 1. }
+    }
+
+    /// Parses `Source`, `-Source` or `Source * scale`.
+    pub fn parse(binding: &str) -> Result<Self> {
+        let (source, scale) = match binding.split_once('*') {
+            Some((source, scale)) => {
+                let scale = scale
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid axis scale {:?}", scale.trim()))?;
+                (source.trim(), scale)
+            }
+            None => (binding, 1.),
+        };
+        let (source, scale) = match source.strip_prefix('-') {
+            Some(source) => (source.trim(), -scale),
+            None => (source, scale),
+        };
+        let source = AxisSource::from_name(source)
+            .with_context(|| format!("Unknown axis source {:?}", source))?;
+        Ok(Self { source, scale })
+    }
+
+    pub fn to_config(self) -> String {
+        if self.scale == 1. {
+            self.source.name().to_owned()
+        } else if self.scale == -1. {
+            format!("-{}", self.source.name())
+        } else {
+            format!("{} * {}", self.source.name(), self.scale)
+        }
+    }
+}
+
+macro_rules! keys {
+    ($($key:ident),* $(,)?) => {
+        &[$((stringify!($key), VirtualKeyCode::$key),)*]
+    };
+}
+
+/// Keys that can be bound in config files, named like `VirtualKeyCode`.
+#[rustfmt::skip]
+const KEYS: &[(&str, VirtualKeyCode)] = keys![
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1,
+    Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11,
+    F12, Escape, Space, Return, Tab, Back, Delete, Insert, Home, End, PageUp, PageDown, Left, Up,
+    Right, Down, LShift, RShift, LControl, RControl, LAlt, RAlt, Equals, Minus, Plus, Period,
+    Comma, Slash, Backslash, Semicolon, Apostrophe, Grave, LBracket, RBracket, Numpad0, Numpad1,
+    Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9, NumpadAdd,
+    NumpadSubtract,
+];
+
+const MOUSE_BUTTONS: &[(&str, MouseButton)] = &[
+    ("MouseLeft", MouseButton::Left),
+    ("MouseRight", MouseButton::Right),
+    ("MouseMiddle", MouseButton::Middle),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            Binding::Key(key) => KEYS.iter().find(|(_, k)| *k == key).map(|(name, _)| *name),
+            Binding::Mouse(button) => MOUSE_BUTTONS
+                .iter()
+                .find(|(_, b)| *b == button)
+                .map(|(name, _)| *name),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let key = KEYS.iter().find(|(n, _)| *n == name);
+        let button = MOUSE_BUTTONS.iter().find(|(n, _)| *n == name);
+        match (key, button) {
+            (Some((_, key)), _) => Some(Binding::Key(*key)),
+            (_, Some((_, button))) => Some(Binding::Mouse(*button)),
+            _ => None,
+        }
+    }
+}
+
+/// Which binding triggers which action and what drives each axis. A binding triggers at most one
+/// action, an action can have any number of bindings.
+#[derive(Debug, Clone)]
+pub struct InputMap {
+    bindings: Vec<(Binding, Action)>,
+    /// Axes without a binding stay at zero.
+    axes: Vec<(Axis, AxisBinding)>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::{Key, Mouse};
+        use VirtualKeyCode as K;
+
+        Self {
+            bindings: vec![
+                (Key(K::W), Action::MoveForward),
+                (Key(K::S), Action::MoveBackward),
+                (Key(K::A), Action::MoveLeft),
+                (Key(K::D), Action::MoveRight),
+                (Key(K::E), Action::MoveUp),
+                (Key(K::Q), Action::MoveDown),
+                (Key(K::LShift), Action::Sprint),
+                (Key(K::RShift), Action::Sprint),
+                (Mouse(MouseButton::Left), Action::Rotate),
+                (Mouse(MouseButton::Right), Action::Pan),
+                (Mouse(MouseButton::Middle), Action::Pan),
+                (Key(K::Escape), Action::ReleaseCursor),
+                (Key(K::C), Action::SwitchController),
+                (Key(K::F), Action::Focus),
+                (Key(K::H), Action::SwitchFovAxis),
+                (Key(K::O), Action::CycleProjection),
+                (Key(K::M), Action::CycleSampleCount),
+                (Key(K::X), Action::CycleAntiAliasing),
+                (Key(K::R), Action::ToggleReverseZ),
+                (Key(K::T), Action::CycleToneMapping),
+                (Key(K::Equals), Action::IncreaseExposure),
+                (Key(K::Minus), Action::DecreaseExposure),
+                (Key(K::B), Action::ToggleBloom),
+                (Key(K::V), Action::CyclePresentMode),
+                (Key(K::L), Action::CycleFrameRateCap),
+                (Key(K::Key0), Action::SelectSample0),
+                (Key(K::Key1), Action::SelectSample1),
+                (Key(K::Key2), Action::SelectSample2),
+                (Key(K::Key3), Action::SelectSample3),
+                (Key(K::Key4), Action::SelectSample4),
+                (Key(K::Key5), Action::SelectSample5),
+                (Key(K::Key6), Action::SelectSample6),
+                (Key(K::Key7), Action::SelectSample7),
+                (Key(K::Key8), Action::SelectSample8),
+                (Key(K::Key9), Action::SelectSample9),
+                (Key(K::Down), Action::NextSample),
+                (Key(K::Up), Action::PreviousSample),
+                (Key(K::Tab), Action::ToggleMenu),
+                (Key(K::F3), Action::ToggleProfiler),
+                (Key(K::F4), Action::SaveTrace),
+                (Key(K::P), Action::TogglePause),
+                (Key(K::Period), Action::Step),
+                (Key(K::F2), Action::Rebind),
+            ],
+            axes: vec![
+                (Axis::LookX, AxisBinding::new(AxisSource::MouseX)),
+                (Axis::LookY, AxisBinding::new(AxisSource::MouseY)),
+                (Axis::Zoom, AxisBinding::new(AxisSource::Wheel)),
+            ],
+        }
+    }
+}
+
+impl InputMap {
+    /// Reads `action = Binding, Binding` and `axis = AxisBinding` lines on top of the default
+    /// bindings. Actions and axes listed in the file lose their default bindings, an empty list
+    /// unbinds them. `#` starts a comment.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let config = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read input config {:?}", path))?;
+        Self::parse(&config).with_context(|| format!("Invalid input config {:?}", path))
+    }
+
+    pub fn parse(config: &str) -> Result<Self> {
+        let mut map = Self::default();
+
+        for (number, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, bindings) = line
+                .split_once('=')
+                .with_context(|| format!("Line {}: expected `action = bindings`", number + 1))?;
+            let (name, bindings) = (name.trim(), bindings.trim());
+
+            if let Some(axis) = Axis::from_name(name) {
+                map.axes.retain(|&(a, _)| a != axis);
+                if !bindings.is_empty() {
+                    let binding = AxisBinding::parse(bindings)
+                        .with_context(|| format!("Line {}", number + 1))?;
+                    map.axes.push((axis, binding));
+                }
+                continue;
+            }
+
+            let action = Action::from_name(name).with_context(|| {
+                format!("Line {}: unknown action or axis {:?}", number + 1, name)
+            })?;
+
+            map.unbind_action(action);
+            for binding in bindings.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                match Binding::from_name(binding) {
+                    Some(binding) => map.bind(binding, action),
+                    None => bail!("Line {}: unknown binding {:?}", number + 1, binding),
+                }
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_config())
+            .with_context(|| format!("Failed to write input config {:?}", path))
+    }
+
+    pub fn to_config(&self) -> String {
+        let mut config = String::new();
+        for &action in Action::ALL {
+            let bindings: Vec<_> = self.bindings(action).filter_map(Binding::name).collect();
+            writeln!(config, "{} = {}", action.name(), bindings.join(", ")).unwrap();
+        }
+        for &axis in Axis::ALL {
+            let binding = self.axis_binding(axis).map(AxisBinding::to_config);
+            writeln!(config, "{} = {}", axis.name(), binding.unwrap_or_default()).unwrap();
+        }
+        config
+    }
+
+    /// Binds `binding` to `action`, taking it away from any other action.
+    pub fn bind(&mut self, binding: Binding, action: Action) {
+        self.bindings.retain(|&(b, _)| b != binding);
+        self.bindings.push((binding, action));
+    }
+
+    pub fn unbind_action(&mut self, action: Action) {
+        self.bindings.retain(|&(_, a)| a != action);
+    }
+
+    pub fn bindings(&self, action: Action) -> impl Iterator<Item = Binding> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |&&(_, a)| a == action)
+            .map(|&(b, _)| b)
+    }
+
+    pub fn action(&self, binding: Binding) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|&&(b, _)| b == binding)
+            .map(|&(_, a)| a)
+    }
+
+    pub fn axis_binding(&self, axis: Axis) -> Option<AxisBinding> {
+        self.axes.iter().find(|&&(a, _)| a == axis).map(|&(_, b)| b)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum Rebind {
+    #[default]
+    Idle,
+    SelectAction,
+    SelectBinding(Action),
+    Done,
+}
+
+/// Turns winit events into actions and axes.
+#[derive(Debug, Clone, Default)]
+pub struct Input {
+    map: InputMap,
+    pressed: HashSet<Binding>,
+    /// Raw mouse movement in pixels and scrolling in lines.
+    mouse_motion: (f32, f32),
+    wheel: f32,
+    rebind: Rebind,
+}
+
+impl Input {
+    pub fn new(map: InputMap) -> Self {
+        Self {
+            map,
+            ..Default::default()
+        }
+    }
+
+    pub fn map(&self) -> &InputMap {
+        &self.map
+    }
+
+    /// Swallows the next two bindings released: the first selects the action to rebind, the
+    /// second becomes its new binding.
+    pub fn start_rebind(&mut self) {
+        info!("Rebinding: release a key or mouse button bound to the action to change");
+        self.rebind = Rebind::SelectAction;
+    }
+
+    /// Returns whether a rebind finished since the last call.
+    pub fn take_rebound(&mut self) -> bool {
+        let done = self.rebind == Rebind::Done;
+        if done {
+            self.rebind = Rebind::Idle;
+        }
+        done
+    }
+
+    /// Returns the action a key or mouse button event triggers and whether it was pressed or
+    /// released. Key repeats are ignored.
+    pub fn process_window_event(&mut self, event: &WindowEvent) -> Option<(Action, bool)> {
+        let (binding, state) = match *event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        ..
+                    },
+                ..
+            } => (Binding::Key(key), state),
+            WindowEvent::MouseInput { button, state, .. } => (Binding::Mouse(button), state),
+            _ => return None,
+        };
+
+        let pressed = state == ElementState::Pressed;
+        let changed = if pressed {
+            self.pressed.insert(binding)
+        } else {
+            self.pressed.remove(&binding)
+        };
+        if !changed {
+            return None;
+        }
+
+        match self.rebind {
+            Rebind::Idle | Rebind::Done => (),
+            _ if pressed => return None,
+            _ if self.map.action(binding) == Some(Action::Rebind) => {
+                info!("Rebinding cancelled");
+                self.rebind = Rebind::Idle;
+                return None;
+            }
+            Rebind::SelectAction => {
+                match self.map.action(binding) {
+                    Some(action) => {
+                        info!("Rebinding {}: release the new binding", action.name());
+                        self.rebind = Rebind::SelectBinding(action);
+                    }
+                    None => warn!("{:?} isn't bound to anything", binding),
+                }
+                return None;
+            }
+            Rebind::SelectBinding(action) => {
+                info!("Bound {:?} to {}", binding, action.name());
+                self.map.bind(binding, action);
+                self.rebind = Rebind::Done;
+                return None;
+            }
+        }
+
+        self.map.action(binding).map(|action| (action, pressed))
+    }
+
+    /// Accumulates mouse movement and scrolling until `reset_axes`.
+    pub fn process_device_event(&mut self, event: &DeviceEvent) {
+        match *event {
+            DeviceEvent::MouseMotion { delta: (x, y) } => {
+                self.mouse_motion.0 += x as f32;
+                self.mouse_motion.1 += y as f32;
+            }
+            DeviceEvent::MouseWheel { delta } => {
+                self.wheel += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+                };
+            }
+            _ => (),
+        }
+    }
+
+    pub fn axis(&self, axis: Axis) -> f32 {
+        let binding = match self.map.axis_binding(axis) {
+            Some(binding) => binding,
+            None => return 0.,
+        };
+        let value = match binding.source {
+            AxisSource::MouseX => self.mouse_motion.0,
+            AxisSource::MouseY => self.mouse_motion.1,
+            AxisSource::Wheel => self.wheel,
+        };
+        value * binding.scale
+    }
+
+    /// Call once the axes have been used for the frame.
+    pub fn reset_axes(&mut self) {
+        self.mouse_motion = (0., 0.);
+        self.wheel = 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use winit::event::{DeviceId, ModifiersState};
+
+    use super::*;
+
+    fn key_event(key: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+        #[allow(deprecated)]
+        WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 0,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    /// Presses and releases `key`, returning the actions triggered.
+    fn tap(input: &mut Input, key: VirtualKeyCode) -> Vec<(Action, bool)> {
+        [ElementState::Pressed, ElementState::Released]
+            .into_iter()
+            .filter_map(|state| input.process_window_event(&key_event(key, state)))
+            .collect()
+    }
+
+    fn bindings(map: &InputMap, action: Action) -> Vec<Binding> {
+        map.bindings(action).collect()
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let config = "\
+            focus = G, MouseMiddle # comment
+            step =
+            look_y = -MouseY
+            zoom = Wheel * 0.5
+        ";
+        let map = InputMap::parse(config).unwrap();
+        assert_eq!(
+            bindings(&map, Action::Focus),
+            [
+                Binding::Key(VirtualKeyCode::G),
+                Binding::Mouse(MouseButton::Middle)
+            ]
+        );
+        assert!(bindings(&map, Action::Step).is_empty());
+        let look_y = map.axis_binding(Axis::LookY).unwrap();
+        assert_eq!((look_y.source, look_y.scale), (AxisSource::MouseY, -1.));
+        assert_eq!(map.axis_binding(Axis::Zoom).unwrap().scale, 0.5);
+
+        let reparsed = InputMap::parse(&map.to_config()).unwrap();
+        assert_eq!(reparsed.to_config(), map.to_config());
+        for &action in Action::ALL {
+            assert_eq!(bindings(&reparsed, action), bindings(&map, action));
+        }
+        for &axis in Axis::ALL {
+            assert_eq!(reparsed.axis_binding(axis), map.axis_binding(axis));
+        }
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        for config in [
+            "jump = Space",
+            "focus = Hyperspace",
+            "focus Space",
+            "zoom = Trackball",
+            "zoom = Wheel * fast",
+        ] {
+            let error = InputMap::parse(config).err().unwrap();
+            assert!(format!("{:#}", error).contains("Line 1"), "{:#}", error);
+        }
+    }
+
+    #[test]
+    fn unbound_axes_stay_at_zero() {
+        let map = InputMap::parse("look_x =").unwrap();
+        let mut input = Input::new(map);
+        input.process_device_event(&DeviceEvent::MouseMotion { delta: (3., 4.) });
+        assert_eq!(input.axis(Axis::LookX), 0.);
+        assert_eq!(input.axis(Axis::LookY), 4.);
+    }
+
+    #[test]
+    fn bindings_trigger_one_action() {
+        let map = InputMap::parse("focus = W\nmove_up = Space, Space").unwrap();
+        assert_eq!(
+            map.action(Binding::Key(VirtualKeyCode::W)),
+            Some(Action::Focus)
+        );
+        assert!(bindings(&map, Action::MoveForward).is_empty());
+        assert_eq!(
+            bindings(&map, Action::MoveUp),
+            [Binding::Key(VirtualKeyCode::Space)]
+        );
+    }
+
+    #[test]
+    fn rebinding_adds_a_binding() {
+        let mut input = Input::default();
+        input.start_rebind();
+        assert!(tap(&mut input, VirtualKeyCode::F).is_empty());
+        assert!(tap(&mut input, VirtualKeyCode::G).is_empty());
+        assert!(input.take_rebound());
+        assert!(!input.take_rebound());
+
+        assert_eq!(
+            tap(&mut input, VirtualKeyCode::G),
+            [(Action::Focus, true), (Action::Focus, false)]
+        );
+        assert_eq!(bindings(input.map(), Action::Focus).len(), 2);
+    }
+
+    #[test]
+    fn rebinding_can_be_cancelled() {
+        let mut input = Input::default();
+        input.start_rebind();
+        assert!(tap(&mut input, VirtualKeyCode::F).is_empty());
+        assert!(tap(&mut input, VirtualKeyCode::F2).is_empty());
+        assert!(!input.take_rebound());
+
+        let g = Binding::Key(VirtualKeyCode::G);
+        assert_eq!(input.map().action(g), None);
+        assert_eq!(
+            tap(&mut input, VirtualKeyCode::F),
+            [(Action::Focus, true), (Action::Focus, false)]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! A small wgpu renderer with a render graph, post-processing and a few demo pipelines.
+//!
+//! A frame is drawn by building the pipelines against the renderer's targets and handing them to
+//! `Renderer::render` along with the camera:
+//!
+//! ```no_run
+//! # use antimodern::{entity, renderer, scene};
+//! # use pollster::FutureExt;
+//! # fn main() -> anyhow::Result<()> {
+//! # let event_loop = winit::event_loop::EventLoop::new();
+//! # let window = winit::window::Window::new(&event_loop)?;
+//! let adapter_options = renderer::adapter::AdapterOptions::default();
+//! let instance = wgpu::Instance::new(adapter_options.backends);
+//! let mut renderer = renderer::Renderer::new(&instance, &window, adapter_options).block_on()?;
+//!
+//! let scene = scene::default();
+//! let mut cube = renderer::cube::PipelineState::new(
+//!     renderer.device(),
+//!     renderer.pipeline_cache(),
+//!     &renderer.target_config(),
+//!     renderer.view(),
+//!     &scene,
+//! );
+//!
+//! cube.update(renderer.queue(), &scene);
+//! renderer.render(&scene.camera, 0., &mut [&mut cube])?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! See `examples/` for complete programs.
+
+pub mod app;
+pub mod controller;
+pub mod entity;
+pub mod input;
+pub mod renderer;
+pub mod sample;
+pub mod scene;
+pub mod time;
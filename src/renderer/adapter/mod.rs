@@ -0,0 +1,156 @@
+use anyhow::{bail, Context, Result};
+use log::info;
+
+/// Picks an adapter from `Instance::enumerate_adapters` instead of letting wgpu choose.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelector {
+    Index(usize),
+    /// Case insensitive part of the adapter name.
+    Name(String),
+}
+
+impl AdapterSelector {
+    /// Numbers select by index, anything else by name.
+    pub fn parse(selector: &str) -> Self {
+        match selector.parse() {
+            Ok(index) => AdapterSelector::Index(index),
+            Err(_) => AdapterSelector::Name(selector.to_lowercase()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdapterOptions {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    pub selector: Option<AdapterSelector>,
+    /// Uses a software adapter, ignoring `power_preference`.
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for AdapterOptions {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::PRIMARY,
+            power_preference: wgpu::PowerPreference::default(),
+            selector: None,
+            force_fallback_adapter: false,
+        }
+    }
+}
+
+impl AdapterOptions {
+    /// Defaults overridden by the `WGPU_BACKEND`, `WGPU_POWER_PREF` and `WGPU_ADAPTER_NAME`
+    /// variables wgpu's examples use.
+    pub fn from_env() -> Result<Self> {
+        let mut options = Self::default();
+        if let Some(backends) = backends_from_env()? {
+            options.backends = backends;
+        }
+        if let Some(power_preference) = wgpu::util::power_preference_from_env() {
+            options.power_preference = power_preference;
+        }
+        options.selector = selector_from_env();
+        Ok(options)
+    }
+}
+
+/// The backends in `WGPU_BACKEND`, if set.
+pub fn backends_from_env() -> Result<Option<wgpu::Backends>> {
+    match std::env::var("WGPU_BACKEND") {
+        Ok(backends) => parse_backends(&backends)
+            .map(Some)
+            .context("Invalid WGPU_BACKEND"),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Selects the adapter by the name in `WGPU_ADAPTER_NAME`, if set.
+pub fn selector_from_env() -> Option<AdapterSelector> {
+    std::env::var("WGPU_ADAPTER_NAME")
+        .ok()
+        .map(|name| AdapterSelector::Name(name.to_lowercase()))
+}
+
+/// Comma separated backend names like `vulkan,gl`, or one of `primary`, `secondary` and `all`.
+pub fn parse_backends(backends: &str) -> Result<wgpu::Backends> {
+    let parsed = match backends.to_lowercase().as_str() {
+        "primary" => wgpu::Backends::PRIMARY,
+        "secondary" => wgpu::Backends::SECONDARY,
+        "all" => wgpu::Backends::all(),
+        backends => wgpu::util::parse_backends_from_comma_list(backends),
+    };
+    if parsed.is_empty() {
+        bail!("No known backend in {:?}", backends);
+    }
+    Ok(parsed)
+}
+
+pub fn parse_power_preference(power_preference: &str) -> Result<wgpu::PowerPreference> {
+    match power_preference.to_lowercase().as_str() {
+        "low" => Ok(wgpu::PowerPreference::LowPower),
+        "high" => Ok(wgpu::PowerPreference::HighPerformance),
+        _ => bail!(
+            "Unknown power preference {:?}, expected low or high",
+            power_preference
+        ),
+    }
+}
+
+pub async fn request_adapter(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+    options: &AdapterOptions,
+) -> Result<wgpu::Adapter> {
+    let adapter = match &options.selector {
+        Some(selector) => {
+            let mut adapters = instance.enumerate_adapters(options.backends);
+            let adapter = match selector {
+                AdapterSelector::Index(index) => adapters.nth(*index),
+                AdapterSelector::Name(name) => {
+                    adapters.find(|a| a.get_info().name.to_lowercase().contains(name))
+                }
+            }
+            .with_context(|| format!("No adapter matches {:?}", selector))?;
+            if !adapter.is_surface_supported(surface) {
+                bail!("{} can't present to the window", adapter.get_info().name);
+            }
+            adapter
+        }
+        None => instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: options.power_preference,
+                compatible_surface: Some(surface),
+                force_fallback_adapter: options.force_fallback_adapter,
+            })
+            .await
+            .context("No adapter found")?,
+    };
+
+    let info = adapter.get_info();
+    info!(
+        "Using {} ({:?}, {:?})",
+        info.name, info.backend, info.device_type
+    );
+    Ok(adapter)
+}
+
+/// Prints every adapter of `backends` with the index `AdapterSelector::Index` selects it by.
+pub fn list_adapters(instance: &wgpu::Instance, backends: wgpu::Backends) {
+    let adapters: Vec<_> = instance.enumerate_adapters(backends).collect();
+    if adapters.is_empty() {
+        println!("No adapters found for {:?}", backends);
+    }
+    for (index, adapter) in adapters.iter().enumerate() {
+        let info = adapter.get_info();
+        println!("{}: {}", index, info.name);
+        println!("    Backend: {:?}", info.backend);
+        println!("    Type: {:?}", info.device_type);
+        println!(
+            "    Vendor: {:#06x}, device: {:#06x}",
+            info.vendor, info.device
+        );
+        println!("    Features: {:?}", adapter.features());
+        println!("    Limits: {:?}", adapter.limits());
+    }
+}
@@ -1,35 +1,50 @@
 struct Uniforms {
-    mv_mat: mat4x4<f32>;
-    p_mat: mat4x4<f32>;
+    m_mat: mat4x4<f32>;
+    previous_m_mat: mat4x4<f32>;
 };
 
-@group(0) @binding(0)
+@group(1) @binding(0)
 var<uniform> uniforms: Uniforms;
 
 struct VertexOut {
     @builtin(position) position: vec4<f32>;
     @location(0) color: vec4<f32>;
+    @location(1) clip_position: vec4<f32>;
+    @location(2) previous_clip_position: vec4<f32>;
 };
 
 @stage(vertex)
 fn vs_main(
     @location(0) vertex_position: vec3<f32>,
 ) -> VertexOut {
-    let instance_position = vec3<f32>(0.0, 0.0, 0.0);
+    let instance_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    let offset = vec4<f32>(vertex_position, 0.0);
 
-    var position = uniforms.mv_mat * vec4<f32>(instance_position, 1.0);
-    position += vec4<f32>(vertex_position, 1.0);
+    var position = view.view * uniforms.m_mat * instance_position;
+    position += offset;
+    let previous_position =
+        view.previous_view * uniforms.previous_m_mat * instance_position + offset;
 
     var out: VertexOut;
-    out.position = uniforms.p_mat * position;
+    out.position = view.projection * position;
     out.color = vec4<f32>(vec3<f32>(vertex_position + 0.5), 1.0);
+    out.clip_position = out.position;
+    out.previous_clip_position = view.previous_projection * previous_position;
     
     return out;
 }
 
 @stage(fragment)
 fn fs_main(
-  @location(0) color: vec4<f32>
+  vertex: VertexOut
 ) -> @location(0) vec4<f32> {
-    return color;
+    return vertex.color;
+}
+
+@stage(fragment)
+fn fs_motion(
+  vertex: VertexOut
+) -> MotionOut {
+    let motion = motion_vector(vertex.clip_position, vertex.previous_clip_position);
+    return MotionOut(vertex.color, motion);
 }
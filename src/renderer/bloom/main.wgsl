@@ -0,0 +1,134 @@
+struct Uniforms {
+    threshold: f32;
+    knee: f32;
+    radius: f32;
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+@group(0) @binding(1)
+var linear_sampler: sampler;
+@group(0) @binding(2)
+var source: texture_2d<f32>;
+@group(0) @binding(3)
+var base: texture_2d<f32>;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>;
+    @location(0) uv: vec2<f32>;
+};
+
+@stage(vertex)
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> VertexOut {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+
+    var out: VertexOut;
+    out.position = vec4<f32>(uv * 2.0 - vec2<f32>(1.0), 0.0, 1.0);
+    out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn sample_source(uv: vec2<f32>, step: vec2<f32>, x: f32, y: f32) -> vec3<f32> {
+    return textureSample(source, linear_sampler, uv + step * vec2<f32>(x, y)).rgb;
+}
+
+// Weights each 2x2 box by the inverse of its luminance so that single bright pixels don't
+// flicker through the whole chain.
+fn karis_box(a: vec3<f32>, b: vec3<f32>, c: vec3<f32>, d: vec3<f32>, weight: f32) -> vec4<f32> {
+    let color = (a + b + c + d) * 0.25;
+    let w = weight / (1.0 + luminance(color));
+    return vec4<f32>(color * w, w);
+}
+
+fn threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - uniforms.threshold + uniforms.knee, 0.0, 2.0 * uniforms.knee);
+    soft = soft * soft / (4.0 * uniforms.knee + 0.00001);
+    let contribution = max(soft, brightness - uniforms.threshold) / max(brightness, 0.00001);
+    return color * contribution;
+}
+
+@stage(fragment)
+fn fs_prefilter(
+    @location(0) uv: vec2<f32>,
+) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+
+    let a = sample_source(uv, texel, -2.0, 2.0);
+    let b = sample_source(uv, texel, 0.0, 2.0);
+    let c = sample_source(uv, texel, 2.0, 2.0);
+    let d = sample_source(uv, texel, -2.0, 0.0);
+    let e = sample_source(uv, texel, 0.0, 0.0);
+    let f = sample_source(uv, texel, 2.0, 0.0);
+    let g = sample_source(uv, texel, -2.0, -2.0);
+    let h = sample_source(uv, texel, 0.0, -2.0);
+    let i = sample_source(uv, texel, 2.0, -2.0);
+    let j = sample_source(uv, texel, -1.0, 1.0);
+    let k = sample_source(uv, texel, 1.0, 1.0);
+    let l = sample_source(uv, texel, -1.0, -1.0);
+    let m = sample_source(uv, texel, 1.0, -1.0);
+
+    let sum = karis_box(j, k, l, m, 0.5)
+        + karis_box(a, b, d, e, 0.125)
+        + karis_box(b, c, e, f, 0.125)
+        + karis_box(d, e, g, h, 0.125)
+        + karis_box(e, f, h, i, 0.125);
+
+    return vec4<f32>(threshold(sum.rgb / sum.a), 1.0);
+}
+
+@stage(fragment)
+fn fs_downsample(
+    @location(0) uv: vec2<f32>,
+) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+
+    let a = sample_source(uv, texel, -2.0, 2.0);
+    let b = sample_source(uv, texel, 0.0, 2.0);
+    let c = sample_source(uv, texel, 2.0, 2.0);
+    let d = sample_source(uv, texel, -2.0, 0.0);
+    let e = sample_source(uv, texel, 0.0, 0.0);
+    let f = sample_source(uv, texel, 2.0, 0.0);
+    let g = sample_source(uv, texel, -2.0, -2.0);
+    let h = sample_source(uv, texel, 0.0, -2.0);
+    let i = sample_source(uv, texel, 2.0, -2.0);
+    let j = sample_source(uv, texel, -1.0, 1.0);
+    let k = sample_source(uv, texel, 1.0, 1.0);
+    let l = sample_source(uv, texel, -1.0, -1.0);
+    let m = sample_source(uv, texel, 1.0, -1.0);
+
+    let color = e * 0.125
+        + (a + c + g + i) * 0.03125
+        + (b + d + f + h) * 0.0625
+        + (j + k + l + m) * 0.125;
+
+    return vec4<f32>(color, 1.0);
+}
+
+@stage(fragment)
+fn fs_upsample(
+    @location(0) uv: vec2<f32>,
+) -> @location(0) vec4<f32> {
+    let dimensions = vec2<f32>(textureDimensions(source));
+    let offset = vec2<f32>(uniforms.radius * dimensions.y / dimensions.x, uniforms.radius);
+
+    let a = sample_source(uv, offset, -1.0, 1.0);
+    let b = sample_source(uv, offset, 0.0, 1.0);
+    let c = sample_source(uv, offset, 1.0, 1.0);
+    let d = sample_source(uv, offset, -1.0, 0.0);
+    let e = sample_source(uv, offset, 0.0, 0.0);
+    let f = sample_source(uv, offset, 1.0, 0.0);
+    let g = sample_source(uv, offset, -1.0, -1.0);
+    let h = sample_source(uv, offset, 0.0, -1.0);
+    let i = sample_source(uv, offset, 1.0, -1.0);
+
+    let blurred = (e * 4.0 + (b + d + f + h) * 2.0 + (a + c + g + i)) / 16.0;
+
+    return vec4<f32>(textureSample(base, linear_sampler, uv).rgb + blurred, 1.0);
+}
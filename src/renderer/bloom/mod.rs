@@ -0,0 +1,340 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::renderer::{self, graph, uniform::UniformBuffer};
+
+pub const MIP_COUNT: usize = 6;
+
+// Passes are named after the texture they write.
+const DOWNSAMPLED: [graph::ResourceId; MIP_COUNT] = [
+    "bloom_downsample_0",
+    "bloom_downsample_1",
+    "bloom_downsample_2",
+    "bloom_downsample_3",
+    "bloom_downsample_4",
+    "bloom_downsample_5",
+];
+const UPSAMPLED: [graph::ResourceId; MIP_COUNT - 1] = [
+    "bloom_upsample_0",
+    "bloom_upsample_1",
+    "bloom_upsample_2",
+    "bloom_upsample_3",
+    "bloom_upsample_4",
+];
+
+/// Sum of all the blurred mips at half the resolution of the input.
+pub const OUTPUT: graph::ResourceId = UPSAMPLED[0];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Settings {
+    pub enabled: bool,
+    /// Brightness below which pixels don't bloom, with a soft knee of half the threshold.
+    pub threshold: f32,
+    /// Amount of the blurred image mixed into the scene before tone mapping.
+    pub intensity: f32,
+    /// Upsampling filter radius, as a fraction of the screen height.
+    pub radius: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: 0.,
+            intensity: 0.04,
+            radius: 0.005,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+#[repr(C)]
+struct Uniforms {
+    threshold: f32,
+    knee: f32,
+    radius: f32,
+    _pad0: [u8; 4],
+}
+
+impl Uniforms {
+    fn new(settings: &Settings) -> Self {
+        Self {
+            threshold: settings.threshold,
+            knee: settings.threshold * 0.5,
+            radius: settings.radius,
+            ..Default::default()
+        }
+    }
+}
+
+struct RenderPipelines {
+    prefilter: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    upsample: wgpu::RenderPipeline,
+}
+
+pub struct PipelineState {
+    input: graph::ResourceId,
+    settings: Settings,
+    uniform_buffer: UniformBuffer<Uniforms>,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_groups: Vec<wgpu::BindGroup>,
+    bind_group_generation: u64,
+    render_pipelines: RenderPipelines,
+}
+
+impl PipelineState {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    pub fn new(device: &wgpu::Device, input: graph::ResourceId) -> Self {
+        let settings = Settings::default();
+
+        let uniform_buffer =
+            UniformBuffer::new(device, "Uniform buffer", &Uniforms::new(&settings));
+        let sampler = Self::make_sampler(device);
+        let bind_group_layout = Self::make_bind_group_layout(device);
+        let render_pipelines = Self::make_render_pipelines(device, &bind_group_layout);
+
+        Self {
+            input,
+            settings,
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            bind_groups: Vec::new(),
+            bind_group_generation: 0,
+            render_pipelines,
+        }
+    }
+
+    fn make_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        })
+    }
+
+    fn make_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: UniformBuffer::<Uniforms>::binding_size(),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2),
+                texture_entry(3),
+            ],
+        })
+    }
+
+    fn make_bind_group(
+        &self,
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        base: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(base),
+                },
+            ],
+        })
+    }
+
+    fn make_render_pipelines(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> RenderPipelines {
+        let shader_module = device.create_shader_module(&wgpu::include_wgsl!("main.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let make_render_pipeline = |entry_point| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point,
+                    targets: &[Self::FORMAT.into()],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        RenderPipelines {
+            prefilter: make_render_pipeline("fs_prefilter"),
+            downsample: make_render_pipeline("fs_downsample"),
+            upsample: make_render_pipeline("fs_upsample"),
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: Settings) {
+        self.settings = settings;
+        self.uniform_buffer.write(queue, &Uniforms::new(&settings));
+    }
+
+    pub fn set_input(&mut self, input: graph::ResourceId) {
+        self.input = input;
+        self.bind_groups.clear();
+    }
+
+    /// Source of each upsampling pass: the next smaller mip, which for the smallest one is the
+    /// last downsampled texture.
+    fn upsample_source(mip: usize) -> graph::ResourceId {
+        if mip + 1 == UPSAMPLED.len() {
+            DOWNSAMPLED[mip + 1]
+        } else {
+            UPSAMPLED[mip + 1]
+        }
+    }
+
+    /// Rebinds the mip chain after the graph reallocated it.
+    pub fn update(&mut self, device: &wgpu::Device, graph: &graph::RenderGraph) {
+        if !self.bind_groups.is_empty() && self.bind_group_generation == graph.generation() {
+            return;
+        }
+
+        let downsample_sources = Some(self.input).into_iter().chain(DOWNSAMPLED);
+        let bind_groups = downsample_sources
+            .take(MIP_COUNT)
+            .map(|source| (source, source))
+            .chain((0..UPSAMPLED.len()).map(|mip| (Self::upsample_source(mip), DOWNSAMPLED[mip])))
+            .map(|(source, base)| {
+                Some(self.make_bind_group(device, graph.view(source)?, graph.view(base)?))
+            })
+            .collect::<Option<Vec<_>>>();
+
+        self.bind_groups = bind_groups.unwrap_or_default();
+        self.bind_group_generation = graph.generation();
+    }
+}
+
+impl renderer::Pipeline for PipelineState {
+    fn setup(&self, graph: &mut graph::RenderGraph) {
+        for (mip, id) in DOWNSAMPLED.into_iter().enumerate() {
+            let desc = graph::TextureDesc {
+                scale: 0.5f32.powi(mip as i32 + 1),
+                ..graph::TextureDesc::new(Self::FORMAT)
+            };
+            graph.add_texture(id, desc);
+            if let Some(&id) = UPSAMPLED.get(mip) {
+                graph.add_texture(id, desc);
+            }
+        }
+
+        if !self.settings.enabled {
+            return;
+        }
+
+        let pass = |target, reads| graph::PassDesc {
+            name: target,
+            color_attachments: vec![graph::ColorAttachment {
+                target,
+                resolve_target: None,
+                clear: Some(wgpu::Color::BLACK),
+            }],
+            depth_attachment: None,
+            reads,
+        };
+
+        graph.add_pass(pass(DOWNSAMPLED[0], vec![self.input]));
+        for mip in 1..MIP_COUNT {
+            graph.add_pass(pass(DOWNSAMPLED[mip], vec![DOWNSAMPLED[mip - 1]]));
+        }
+        for mip in (0..UPSAMPLED.len()).rev() {
+            graph.add_pass(pass(
+                UPSAMPLED[mip],
+                vec![Self::upsample_source(mip), DOWNSAMPLED[mip]],
+            ));
+        }
+        for pass in DOWNSAMPLED.into_iter().chain(UPSAMPLED) {
+            graph.add_draw(pass, self.name());
+        }
+    }
+
+    fn render<'a>(&'a self, pass: &str, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.bind_groups.is_empty() {
+            return;
+        }
+
+        let (render_pipeline, bind_group) =
+            if let Some(mip) = DOWNSAMPLED.iter().position(|&id| id == pass) {
+                let render_pipeline = if mip == 0 {
+                    &self.render_pipelines.prefilter
+                } else {
+                    &self.render_pipelines.downsample
+                };
+                (render_pipeline, &self.bind_groups[mip])
+            } else if let Some(mip) = UPSAMPLED.iter().position(|&id| id == pass) {
+                (
+                    &self.render_pipelines.upsample,
+                    &self.bind_groups[MIP_COUNT + mip],
+                )
+            } else {
+                return;
+            };
+
+        render_pass.set_pipeline(render_pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
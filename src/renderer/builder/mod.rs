@@ -0,0 +1,394 @@
+use std::ops::Range;
+
+use bytemuck::Pod;
+
+use crate::renderer::{
+    self,
+    cache::{CachedPipeline, PipelineCache, PipelineKey},
+    uniform::UniformBuffer,
+    view,
+};
+
+/// Group of the bindings added to a `PipelineBuilder`, after the view's.
+pub const BIND_GROUP: u32 = 1;
+
+/// Tightly packed `Vec3` positions at location 0.
+pub const POSITIONS: wgpu::VertexBufferLayout<'static> = wgpu::VertexBufferLayout {
+    array_stride: 12,
+    step_mode: wgpu::VertexStepMode::Vertex,
+    attributes: &[wgpu::VertexAttribute {
+        format: wgpu::VertexFormat::Float32x3,
+        offset: 0,
+        shader_location: 0,
+    }],
+};
+
+/// A render bundle along with the targets and `K`, anything else it depends on, it was recorded
+/// for. Render bundles bake in the target formats and sample count, so they have to be recorded
+/// again when those change.
+pub struct RecordedBundle<K = ()> {
+    recorded: Option<(wgpu::RenderBundle, renderer::TargetConfig, K)>,
+}
+
+impl<K> Default for RecordedBundle<K> {
+    fn default() -> Self {
+        Self { recorded: None }
+    }
+}
+
+impl<K: PartialEq> RecordedBundle<K> {
+    /// Whether the bundle was recorded for `target_config` and `key`.
+    pub fn is_current(&self, target_config: &renderer::TargetConfig, key: &K) -> bool {
+        matches!(&self.recorded, Some((_, t, k)) if t == target_config && k == key)
+    }
+
+    pub fn set(
+        &mut self,
+        bundle: wgpu::RenderBundle,
+        target_config: &renderer::TargetConfig,
+        key: K,
+    ) {
+        self.recorded = Some((bundle, *target_config, key));
+    }
+
+    pub fn bundle(&self) -> Option<&wgpu::RenderBundle> {
+        self.recorded.as_ref().map(|(bundle, _, _)| bundle)
+    }
+}
+
+/// Builds a render pipeline for the main pass, or takes it from the `PipelineCache`, and records
+/// a bundle drawing with it.
+///
+/// The shader is prepended with `view.wgsl`, the view binding is group 0 and the bindings added
+/// here are `BIND_GROUP`. When the targets have motion vectors, `fs_motion` is used instead of
+/// `fs_main` and also writes `MotionOut::motion`, see `motion_vector` in `view.wgsl`. Pipelines
+/// default to opaque, depth tested and written triangle lists with back faces culled.
+pub struct PipelineBuilder<'a> {
+    label: &'a str,
+    source: &'a str,
+    vertex_buffers: Vec<(wgpu::VertexBufferLayout<'a>, &'a wgpu::Buffer)>,
+    index_buffer: Option<(&'a wgpu::Buffer, wgpu::IndexFormat)>,
+    bindings: Vec<(wgpu::BindGroupLayoutEntry, wgpu::BindingResource<'a>)>,
+    primitive: wgpu::PrimitiveState,
+    blend: Option<wgpu::BlendState>,
+    depth_write_enabled: bool,
+    /// Indices with an index buffer, vertices otherwise.
+    elements: Range<u32>,
+    instances: Range<u32>,
+}
+
+impl<'a> PipelineBuilder<'a> {
+    /// `source` has `vs_main`, `fs_main` and `fs_motion` entry points.
+    pub fn new(label: &'a str, source: &'a str) -> Self {
+        Self {
+            label,
+            source,
+            vertex_buffers: Vec::new(),
+            index_buffer: None,
+            bindings: Vec::new(),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            blend: None,
+            depth_write_enabled: true,
+            elements: 0..0,
+            instances: 0..1,
+        }
+    }
+
+    /// Adds a vertex buffer at the next slot.
+    pub fn vertex_buffer(
+        mut self,
+        layout: wgpu::VertexBufferLayout<'a>,
+        buffer: &'a wgpu::Buffer,
+    ) -> Self {
+        self.vertex_buffers.push((layout, buffer));
+        self
+    }
+
+    /// Draws `indices` of `buffer` instead of vertices.
+    pub fn index_buffer(
+        mut self,
+        buffer: &'a wgpu::Buffer,
+        format: wgpu::IndexFormat,
+        indices: Range<u32>,
+    ) -> Self {
+        self.index_buffer = Some((buffer, format));
+        self.elements = indices;
+        self
+    }
+
+    pub fn binding(
+        mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        ty: wgpu::BindingType,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        let entry = wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        };
+        self.bindings.push((entry, resource));
+        self
+    }
+
+    pub fn uniform<T: Pod>(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        buffer: &'a UniformBuffer<T>,
+    ) -> Self {
+        let ty = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: UniformBuffer::<T>::binding_size(),
+        };
+        self.binding(binding, visibility, ty, buffer.as_entire_binding())
+    }
+
+    /// Read only storage buffer of `T`s.
+    pub fn storage<T>(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        buffer: &'a wgpu::Buffer,
+    ) -> Self {
+        let ty = wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<T>() as _),
+        };
+        self.binding(binding, visibility, ty, buffer.as_entire_binding())
+    }
+
+    pub fn primitive(mut self, primitive: wgpu::PrimitiveState) -> Self {
+        self.primitive = primitive;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.primitive.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn blend(mut self, blend: wgpu::BlendState) -> Self {
+        self.blend = Some(blend);
+        self
+    }
+
+    /// Depth testing stays enabled, transparent geometry usually only skips the write.
+    pub fn depth_write(mut self, depth_write_enabled: bool) -> Self {
+        self.depth_write_enabled = depth_write_enabled;
+        self
+    }
+
+    /// Draws `vertices` when there is no index buffer.
+    pub fn vertices(mut self, vertices: Range<u32>) -> Self {
+        self.elements = vertices;
+        self
+    }
+
+    pub fn instances(mut self, instances: Range<u32>) -> Self {
+        self.instances = instances;
+        self
+    }
+
+    pub fn build(
+        &self,
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        target_config: &renderer::TargetConfig,
+        view: &view::ViewBinding,
+    ) -> wgpu::RenderBundle {
+        let source = [include_str!("../view/view.wgsl"), self.source].concat();
+        let shader = PipelineCache::shader_hash(&source);
+        let pipeline = pipeline_cache.pipeline(self.label, self.key(shader, target_config), || {
+            let shader_module = pipeline_cache.shader_module(device, self.label, shader, &source);
+            let bind_group_layout = self.make_bind_group_layout(device);
+            let render_pipeline = self.make_render_pipeline(
+                device,
+                view,
+                &shader_module,
+                &bind_group_layout,
+                target_config,
+            );
+            CachedPipeline {
+                bind_group_layout,
+                render_pipeline,
+            }
+        });
+        let bind_group = self.make_bind_group(device, &pipeline.bind_group_layout);
+        self.make_render_bundle(
+            device,
+            target_config,
+            view,
+            &pipeline.render_pipeline,
+            &bind_group,
+        )
+    }
+
+    fn key(&self, shader: u64, target_config: &renderer::TargetConfig) -> PipelineKey {
+        PipelineKey {
+            shader,
+            vertex_buffers: self
+                .vertex_buffers
+                .iter()
+                .map(|(layout, _)| {
+                    (
+                        layout.array_stride,
+                        layout.step_mode,
+                        layout.attributes.to_vec(),
+                    )
+                })
+                .collect(),
+            bindings: self.bindings.iter().map(|(entry, _)| *entry).collect(),
+            primitive: self.primitive,
+            blend: self.blend,
+            depth_write_enabled: self.depth_write_enabled,
+            target_config: *target_config,
+        }
+    }
+
+    fn make_bind_group_layout(&self, device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let entries: Vec<_> = self.bindings.iter().map(|(entry, _)| *entry).collect();
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(self.label),
+            entries: &entries,
+        })
+    }
+
+    fn make_bind_group(
+        &self,
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        let entries: Vec<_> = self
+            .bindings
+            .iter()
+            .map(|(entry, resource)| wgpu::BindGroupEntry {
+                binding: entry.binding,
+                resource: resource.clone(),
+            })
+            .collect();
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(self.label),
+            layout: bind_group_layout,
+            entries: &entries,
+        })
+    }
+
+    fn make_render_pipeline(
+        &self,
+        device: &wgpu::Device,
+        view: &view::ViewBinding,
+        shader_module: &wgpu::ShaderModule,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        target_config: &renderer::TargetConfig,
+    ) -> wgpu::RenderPipeline {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[view.bind_group_layout(), bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_buffers: Vec<_> = self
+            .vertex_buffers
+            .iter()
+            .map(|(layout, _)| layout.clone())
+            .collect();
+
+        let mut targets = vec![wgpu::ColorTargetState {
+            format: target_config.color_format,
+            blend: self.blend,
+            write_mask: wgpu::ColorWrites::ALL,
+        }];
+        let entry_point = match target_config.motion_vector_format {
+            Some(format) => {
+                // Blended geometry doesn't own the pixel, what's behind it keeps its motion.
+                targets.push(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: if self.blend.is_some() {
+                        wgpu::ColorWrites::empty()
+                    } else {
+                        wgpu::ColorWrites::ALL
+                    },
+                });
+                "fs_motion"
+            }
+            None => "fs_main",
+        };
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(self.label),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: "vs_main",
+                buffers: &vertex_buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader_module,
+                entry_point,
+                targets: &targets,
+            }),
+            primitive: self.primitive,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: target_config.depth_format,
+                depth_write_enabled: self.depth_write_enabled,
+                depth_compare: target_config.depth_compare(),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: target_config.sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        })
+    }
+
+    fn make_render_bundle(
+        &self,
+        device: &wgpu::Device,
+        target_config: &renderer::TargetConfig,
+        view: &view::ViewBinding,
+        render_pipeline: &wgpu::RenderPipeline,
+        bind_group: &wgpu::BindGroup,
+    ) -> wgpu::RenderBundle {
+        let mut encoder =
+            device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                label: Some(self.label),
+                color_formats: &target_config.color_formats(),
+                depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                    format: target_config.depth_format,
+                    depth_read_only: false,
+                    stencil_read_only: true,
+                }),
+                sample_count: target_config.sample_count,
+                multiview: None,
+            });
+
+        encoder.set_pipeline(render_pipeline);
+        encoder.set_bind_group(view::BIND_GROUP, view.bind_group(), &[]);
+        encoder.set_bind_group(BIND_GROUP, bind_group, &[]);
+        for (slot, (_, buffer)) in self.vertex_buffers.iter().enumerate() {
+            encoder.set_vertex_buffer(slot as _, buffer.slice(..));
+        }
+        match self.index_buffer {
+            Some((buffer, format)) => {
+                encoder.set_index_buffer(buffer.slice(..), format);
+                encoder.draw_indexed(self.elements.clone(), 0, self.instances.clone());
+            }
+            None => encoder.draw(self.elements.clone(), self.instances.clone()),
+        }
+
+        encoder.finish(&wgpu::RenderBundleDescriptor {
+            label: Some(self.label),
+        })
+    }
+}
@@ -0,0 +1,114 @@
+//! Shader modules and render pipelines shared by everything built with the same source and state.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use log::{debug, info};
+
+use crate::renderer;
+
+/// Everything a render pipeline depends on, with the shader source reduced to its hash.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub shader: u64,
+    /// Stride, step mode and attributes of each vertex buffer.
+    pub vertex_buffers: Vec<(
+        wgpu::BufferAddress,
+        wgpu::VertexStepMode,
+        Vec<wgpu::VertexAttribute>,
+    )>,
+    pub bindings: Vec<wgpu::BindGroupLayoutEntry>,
+    pub primitive: wgpu::PrimitiveState,
+    pub blend: Option<wgpu::BlendState>,
+    pub depth_write_enabled: bool,
+    pub target_config: renderer::TargetConfig,
+}
+
+/// A render pipeline and the layout of the bind group it takes after the view's.
+pub struct CachedPipeline {
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub render_pipeline: wgpu::RenderPipeline,
+}
+
+/// Keeps every shader module and pipeline built through it for as long as the device lives, so
+/// samples selected again and targets switched back reuse them instead of compiling again.
+///
+/// wgpu doesn't expose the driver's pipeline binaries yet, so nothing is persisted to disk.
+#[derive(Default)]
+pub struct PipelineCache {
+    shader_modules: Mutex<HashMap<u64, Arc<wgpu::ShaderModule>>>,
+    pipelines: Mutex<HashMap<PipelineKey, Arc<CachedPipeline>>>,
+}
+
+impl PipelineCache {
+    pub fn shader_hash(source: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// The module compiled from `source`, which hashes to `shader`.
+    pub fn shader_module(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        shader: u64,
+        source: &str,
+    ) -> Arc<wgpu::ShaderModule> {
+        if let Some(shader_module) = self.shader_modules.lock().unwrap().get(&shader) {
+            return shader_module.clone();
+        }
+
+        let start = Instant::now();
+        let shader_module = Arc::new(device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        }));
+        info!(
+            "Compiled {} shader in {:.2} ms",
+            label,
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+
+        // Another thread may have compiled it meanwhile, the first one stays.
+        self.shader_modules
+            .lock()
+            .unwrap()
+            .entry(shader)
+            .or_insert(shader_module)
+            .clone()
+    }
+
+    /// The pipeline for `key`, built with `build` the first time it is asked for.
+    pub fn pipeline(
+        &self,
+        label: &str,
+        key: PipelineKey,
+        build: impl FnOnce() -> CachedPipeline,
+    ) -> Arc<CachedPipeline> {
+        // Not locked while building, `build` compiles its shader through this cache too.
+        if let Some(pipeline) = self.pipelines.lock().unwrap().get(&key) {
+            debug!("Reusing {} pipeline", label);
+            return pipeline.clone();
+        }
+
+        let start = Instant::now();
+        let pipeline = Arc::new(build());
+        info!(
+            "Built {} pipeline in {:.2} ms",
+            label,
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+
+        self.pipelines
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert(pipeline)
+            .clone()
+    }
+}
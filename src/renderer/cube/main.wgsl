@@ -1,13 +1,16 @@
 struct Uniforms {
-    mvp_matrix: mat4x4<f32>;
+    model_matrix: mat4x4<f32>;
+    previous_model_matrix: mat4x4<f32>;
 };
 
-@group(0) @binding(0)
+@group(1) @binding(0)
 var<uniform> uniforms: Uniforms;
 
 struct VertexOut {
     @builtin(position) position: vec4<f32>;
     @location(0) color: vec4<f32>;
+    @location(1) clip_position: vec4<f32>;
+    @location(2) previous_clip_position: vec4<f32>;
 };
 
 @stage(vertex)
@@ -15,14 +18,25 @@ fn vs_main(
     @location(0) position: vec3<f32>,
 ) -> VertexOut {
     var out: VertexOut;
-    out.position = uniforms.mvp_matrix * vec4<f32>(position, 1.0);
+    out.position = view.view_projection * uniforms.model_matrix * vec4<f32>(position, 1.0);
     out.color = vec4<f32>(position + vec3<f32>(0.5), 1.0);
+    out.clip_position = out.position;
+    out.previous_clip_position = view.previous_view_projection
+        * uniforms.previous_model_matrix * vec4<f32>(position, 1.0);
     return out;
 }
 
 @stage(fragment)
 fn fs_main(
-  @location(0) color: vec4<f32>
+  vertex: VertexOut
 ) -> @location(0) vec4<f32> {
-    return color;
+    return vertex.color;
+}
+
+@stage(fragment)
+fn fs_motion(
+  vertex: VertexOut
+) -> MotionOut {
+    let motion = motion_vector(vertex.clip_position, vertex.previous_clip_position);
+    return MotionOut(vertex.color, motion);
 }
@@ -0,0 +1,72 @@
+@group(0) @binding(0)
+var linear_sampler: sampler;
+@group(0) @binding(1)
+var source: texture_2d<f32>;
+
+@stage(vertex)
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - vec2<f32>(1.0), 0.0, 1.0);
+}
+
+let REDUCE_MIN: f32 = 0.0078125;
+let REDUCE_MUL: f32 = 0.125;
+let SPAN_MAX: f32 = 8.0;
+
+fn sample_source(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(source, linear_sampler, uv, 0.0).rgb;
+}
+
+// The source is sampled as linear color, edges are found on perceptual luma.
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+// The cheap variant from Lottes' original FXAA release: one blur along the edge direction,
+// rejected where it pulls in colors from outside the local luma range.
+@stage(fragment)
+fn fs_main(
+    @builtin(position) position: vec4<f32>,
+) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    let uv = position.xy * texel;
+
+    let luma_nw = luma(sample_source(uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_source(uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_source(uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_source(uv + vec2<f32>(1.0, 1.0) * texel));
+    let color_m = sample_source(uv);
+    let luma_m = luma(color_m);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var direction = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let direction_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(
+        direction * inverse_direction_min,
+        vec2<f32>(-SPAN_MAX),
+        vec2<f32>(SPAN_MAX),
+    ) * texel;
+
+    let color_a = 0.5 * (
+        sample_source(uv + direction * (1.0 / 3.0 - 0.5))
+        + sample_source(uv + direction * (2.0 / 3.0 - 0.5))
+    );
+    let color_b = color_a * 0.5 + 0.25 * (
+        sample_source(uv - direction * 0.5)
+        + sample_source(uv + direction * 0.5)
+    );
+
+    let luma_b = luma(color_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return vec4<f32>(color_a, 1.0);
+    }
+    return vec4<f32>(color_b, 1.0);
+}
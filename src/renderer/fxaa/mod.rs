@@ -0,0 +1,158 @@
+use crate::renderer::{self, graph};
+
+pub const PASS: &str = "fxaa";
+
+pub struct PipelineState {
+    input: graph::ResourceId,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: Option<wgpu::BindGroup>,
+    bind_group_generation: u64,
+    render_pipeline: wgpu::RenderPipeline,
+}
+
+impl PipelineState {
+    pub fn new(
+        device: &wgpu::Device,
+        input: graph::ResourceId,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let sampler = Self::make_sampler(device);
+        let bind_group_layout = Self::make_bind_group_layout(device);
+        let render_pipeline = Self::make_render_pipeline(device, &bind_group_layout, output_format);
+
+        Self {
+            input,
+            sampler,
+            bind_group_layout,
+            bind_group: None,
+            bind_group_generation: 0,
+            render_pipeline,
+        }
+    }
+
+    fn make_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("FXAA sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        })
+    }
+
+    fn make_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn make_bind_group(
+        &self,
+        device: &wgpu::Device,
+        input_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(input_view),
+                },
+            ],
+        })
+    }
+
+    fn make_render_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(&wgpu::include_wgsl!("main.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("FXAA"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[output_format.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Rebinds the input texture after the graph reallocated it.
+    pub fn update(&mut self, device: &wgpu::Device, graph: &graph::RenderGraph) {
+        if self.bind_group.is_some() && self.bind_group_generation == graph.generation() {
+            return;
+        }
+        self.bind_group = graph
+            .view(self.input)
+            .map(|input_view| self.make_bind_group(device, input_view));
+        self.bind_group_generation = graph.generation();
+    }
+}
+
+impl renderer::Pipeline for PipelineState {
+    fn setup(&self, graph: &mut graph::RenderGraph) {
+        graph.add_pass(graph::PassDesc {
+            name: PASS,
+            color_attachments: vec![graph::ColorAttachment {
+                target: graph::SURFACE,
+                resolve_target: None,
+                clear: Some(wgpu::Color::BLACK),
+            }],
+            depth_attachment: None,
+            reads: vec![self.input],
+        });
+        graph.add_draw(PASS, self.name());
+    }
+
+    fn render<'a>(&'a self, pass: &str, render_pass: &mut wgpu::RenderPass<'a>) {
+        if pass != PASS {
+            return;
+        }
+        if let Some(bind_group) = &self.bind_group {
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
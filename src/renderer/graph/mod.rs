@@ -0,0 +1,549 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Context, Result};
+
+use crate::renderer::{self, profiler};
+
+pub type ResourceId = &'static str;
+
+pub const SURFACE: ResourceId = "surface";
+pub const DEPTH: ResourceId = "depth";
+
+pub const MAIN_PASS: &str = "main";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TextureDesc {
+    pub format: wgpu::TextureFormat,
+    /// Size relative to the surface.
+    pub scale: f32,
+    pub sample_count: u32,
+    pub usage: wgpu::TextureUsages,
+    /// Keeps the contents across frames. Passes reading a history texture see what was written
+    /// in the previous frame, so they are ordered before the passes writing it.
+    pub history: bool,
+}
+
+impl TextureDesc {
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            scale: 1.,
+            sample_count: 1,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            history: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ColorAttachment {
+    pub target: ResourceId,
+    pub resolve_target: Option<ResourceId>,
+    /// Cleared to this color if this is the first pass writing the target in the frame, loaded
+    /// otherwise.
+    pub clear: Option<wgpu::Color>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DepthAttachment {
+    pub target: ResourceId,
+    pub clear: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PassDesc {
+    pub name: &'static str,
+    pub color_attachments: Vec<ColorAttachment>,
+    pub depth_attachment: Option<DepthAttachment>,
+    pub reads: Vec<ResourceId>,
+}
+
+impl PassDesc {
+    fn writes(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.color_attachments
+            .iter()
+            .flat_map(|a| Some(a.target).into_iter().chain(a.resolve_target))
+            .chain(self.depth_attachment.iter().map(|a| a.target))
+    }
+
+    fn touches(&self, id: ResourceId) -> bool {
+        self.reads.contains(&id) || self.writes().any(|w| w == id)
+    }
+}
+
+struct Texture {
+    desc: TextureDesc,
+    allocation: Option<(wgpu::Texture, wgpu::TextureView)>,
+}
+
+struct CompiledPass {
+    pass: usize,
+    color_ops: Vec<wgpu::Operations<wgpu::Color>>,
+    depth_ops: Option<wgpu::Operations<f32>>,
+}
+
+pub struct RenderGraph {
+    width: u32,
+    height: u32,
+    textures: HashMap<ResourceId, Texture>,
+    passes: Vec<PassDesc>,
+    /// `(pass, pipeline)` pairs, the pipelines drawing in each pass of the current frame.
+    draws: HashSet<(&'static str, &'static str)>,
+    generation: u64,
+}
+
+impl RenderGraph {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            textures: HashMap::new(),
+            passes: Vec::new(),
+            draws: HashSet::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (self.width, self.height) == (width, height) {
+            return;
+        }
+        self.width = width;
+        self.height = height;
+        for texture in self.textures.values_mut() {
+            texture.allocation = None;
+        }
+        self.generation += 1;
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Declares a texture. Declaring it again with a different description reallocates it.
+    pub fn add_texture(&mut self, id: ResourceId, desc: TextureDesc) {
+        match self.textures.get_mut(id) {
+            Some(texture) if texture.desc == desc => (),
+            Some(texture) => {
+                texture.desc = desc;
+                texture.allocation = None;
+                self.generation += 1;
+            }
+            None => {
+                self.textures.insert(
+                    id,
+                    Texture {
+                        desc,
+                        allocation: None,
+                    },
+                );
+                self.generation += 1;
+            }
+        }
+    }
+
+    pub fn remove_texture(&mut self, id: ResourceId) {
+        self.textures.remove(id);
+    }
+
+    /// Adds a pass to the current frame. Passes are cleared after each `execute`.
+    pub fn add_pass(&mut self, pass: PassDesc) {
+        self.passes.push(pass);
+    }
+
+    /// Has the pipeline with this name draw in the pass in the current frame. Pipelines are only
+    /// asked to render in the passes they draw in, draws are cleared after each `execute`.
+    pub fn add_draw(&mut self, pass: &'static str, pipeline: &'static str) {
+        self.draws.insert((pass, pipeline));
+    }
+
+    /// Incremented whenever a texture has to be (re)allocated, so that bind groups referring to
+    /// graph textures know when they have to be rebuilt after the next `allocate`.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn view(&self, id: ResourceId) -> Option<&wgpu::TextureView> {
+        self.textures
+            .get(id)
+            .and_then(|texture| texture.allocation.as_ref())
+            .map(|(_, view)| view)
+    }
+
+    pub fn allocate(&mut self, device: &wgpu::Device) {
+        let Self {
+            width,
+            height,
+            textures,
+            ..
+        } = self;
+
+        for (id, texture) in textures.iter_mut() {
+            if texture.allocation.is_some() {
+                continue;
+            }
+            texture.allocation = Some(Self::create_texture(
+                device,
+                id,
+                &texture.desc,
+                *width,
+                *height,
+            ));
+        }
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        id: ResourceId,
+        desc: &TextureDesc,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(id),
+            size: wgpu::Extent3d {
+                width: ((width as f32 * desc.scale) as u32).max(1),
+                height: ((height as f32 * desc.scale) as u32).max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: desc.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(id),
+            ..Default::default()
+        });
+        (texture, view)
+    }
+
+    fn is_history(&self, id: ResourceId) -> bool {
+        self.textures.get(id).is_some_and(|t| t.desc.history)
+    }
+
+    fn reads_from(&self, reader: &PassDesc, writer: &PassDesc) -> bool {
+        reader
+            .reads
+            .iter()
+            .any(|&id| !self.is_history(id) && writer.writes().any(|w| w == id))
+    }
+
+    fn reads_history_of(&self, reader: &PassDesc, writer: &PassDesc) -> bool {
+        reader
+            .reads
+            .iter()
+            .any(|&id| self.is_history(id) && writer.writes().any(|w| w == id))
+    }
+
+    fn sort(&self) -> Result<Vec<usize>> {
+        let passes = &self.passes;
+
+        // Readers run after writers, except for history reads which have to happen before the
+        // texture is overwritten. Passes writing the same texture keep the order they were added
+        // in.
+        let mut dependencies: Vec<HashSet<usize>> = vec![HashSet::new(); passes.len()];
+        for (i, a) in passes.iter().enumerate() {
+            for (j, b) in passes.iter().enumerate() {
+                if i == j {
+                    continue;
+                }
+                let write_after_write = i < j && b.writes().any(|id| a.writes().any(|w| w == id));
+                if write_after_write || self.reads_from(b, a) || self.reads_history_of(a, b) {
+                    dependencies[j].insert(i);
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(passes.len());
+        let mut done = vec![false; passes.len()];
+        while order.len() < passes.len() {
+            let next = (0..passes.len())
+                .find(|&i| !done[i] && dependencies[i].iter().all(|&d| done[d]))
+                .with_context(|| {
+                    let stuck: Vec<_> = (0..passes.len())
+                        .filter(|&i| !done[i])
+                        .map(|i| passes[i].name)
+                        .collect();
+                    format!("Render graph has a cycle between passes {:?}", stuck)
+                })?;
+            done[next] = true;
+            order.push(next);
+        }
+
+        Ok(order)
+    }
+
+    fn compile(&self) -> Result<Vec<CompiledPass>> {
+        for pass in &self.passes {
+            for id in pass.reads.iter().copied().chain(pass.writes()) {
+                if id != SURFACE && !self.textures.contains_key(id) {
+                    bail!("Pass {:?} uses undeclared texture {:?}", pass.name, id);
+                }
+            }
+            if let Some(id) = pass
+                .reads
+                .iter()
+                .find(|&&id| pass.writes().any(|w| w == id))
+            {
+                bail!("Pass {:?} reads and writes {:?}", pass.name, id);
+            }
+        }
+
+        let order = self.sort()?;
+
+        let used_after = |position: usize, id: ResourceId| {
+            id == SURFACE
+                || self.is_history(id)
+                || order[position + 1..]
+                    .iter()
+                    .any(|&i| self.passes[i].touches(id))
+        };
+
+        let mut written = HashSet::new();
+        let compiled = order
+            .iter()
+            .enumerate()
+            .map(|(position, &i)| {
+                let pass = &self.passes[i];
+
+                let color_ops = pass
+                    .color_attachments
+                    .iter()
+                    .map(|attachment| wgpu::Operations {
+                        load: match attachment.clear {
+                            Some(color) if written.insert(attachment.target) => {
+                                wgpu::LoadOp::Clear(color)
+                            }
+                            _ => wgpu::LoadOp::Load,
+                        },
+                        store: used_after(position, attachment.target),
+                    })
+                    .collect();
+
+                let depth_ops = pass.depth_attachment.map(|attachment| wgpu::Operations {
+                    load: match attachment.clear {
+                        Some(depth) if written.insert(attachment.target) => {
+                            wgpu::LoadOp::Clear(depth)
+                        }
+                        _ => wgpu::LoadOp::Load,
+                    },
+                    store: used_after(position, attachment.target),
+                });
+
+                written.extend(pass.writes());
+
+                CompiledPass {
+                    pass: i,
+                    color_ops,
+                    depth_ops,
+                }
+            })
+            .collect();
+
+        Ok(compiled)
+    }
+
+    fn attachment_view<'a>(
+        &'a self,
+        id: ResourceId,
+        surface_view: &'a wgpu::TextureView,
+    ) -> Result<&'a wgpu::TextureView> {
+        if id == SURFACE {
+            return Ok(surface_view);
+        }
+        self.view(id)
+            .with_context(|| format!("Texture {:?} is not allocated", id))
+    }
+
+    pub fn execute(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        surface_view: &wgpu::TextureView,
+        pipelines: &[&dyn renderer::Pipeline],
+        profiler: &mut profiler::Profiler,
+    ) -> Result<()> {
+        self.allocate(device);
+
+        let compiled = self.compile();
+        let passes = std::mem::take(&mut self.passes);
+        let draws = std::mem::take(&mut self.draws);
+        let compiled = compiled?;
+
+        for CompiledPass {
+            pass,
+            color_ops,
+            depth_ops,
+        } in compiled
+        {
+            let pass = &passes[pass];
+
+            let color_attachments = pass
+                .color_attachments
+                .iter()
+                .zip(color_ops)
+                .map(|(attachment, ops)| {
+                    Ok(wgpu::RenderPassColorAttachment {
+                        view: self.attachment_view(attachment.target, surface_view)?,
+                        resolve_target: attachment
+                            .resolve_target
+                            .map(|id| self.attachment_view(id, surface_view))
+                            .transpose()?,
+                        ops,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let depth_stencil_attachment = match pass.depth_attachment {
+                Some(attachment) => Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.attachment_view(attachment.target, surface_view)?,
+                    depth_ops,
+                    stencil_ops: None,
+                }),
+                None => None,
+            };
+
+            profiler.begin_gpu_scope(encoder, pass.name);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(pass.name),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment,
+            });
+
+            for pipeline in pipelines {
+                if !draws.contains(&(pass.name, pipeline.name())) {
+                    continue;
+                }
+                profiler.begin_gpu_scope(&mut render_pass, pipeline.name());
+                pipeline.render(pass.name, &mut render_pass);
+                profiler.end_gpu_scope(&mut render_pass);
+            }
+            drop(render_pass);
+            profiler.end_gpu_scope(encoder);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: ResourceId = "color";
+    const HISTORY: ResourceId = "history";
+
+    fn graph() -> RenderGraph {
+        let mut graph = RenderGraph::new(64, 64);
+        let format = wgpu::TextureFormat::Rgba16Float;
+        graph.add_texture(COLOR, TextureDesc::new(format));
+        graph.add_texture(
+            HISTORY,
+            TextureDesc {
+                history: true,
+                ..TextureDesc::new(format)
+            },
+        );
+        graph
+    }
+
+    fn pass(name: &'static str, writes: &[ResourceId], reads: &[ResourceId]) -> PassDesc {
+        PassDesc {
+            name,
+            color_attachments: writes
+                .iter()
+                .map(|&target| ColorAttachment {
+                    target,
+                    resolve_target: None,
+                    clear: Some(wgpu::Color::BLACK),
+                })
+                .collect(),
+            depth_attachment: None,
+            reads: reads.to_vec(),
+        }
+    }
+
+    fn order(graph: &RenderGraph) -> Vec<&'static str> {
+        let compiled = graph.compile().unwrap();
+        compiled.iter().map(|c| graph.passes[c.pass].name).collect()
+    }
+
+    #[test]
+    fn readers_run_after_writers() {
+        let mut graph = graph();
+        graph.add_pass(pass("present", &[SURFACE], &[COLOR]));
+        graph.add_pass(pass("main", &[COLOR], &[]));
+        assert_eq!(order(&graph), ["main", "present"]);
+    }
+
+    #[test]
+    fn history_readers_run_before_writers() {
+        let mut graph = graph();
+        graph.add_pass(pass("main", &[COLOR], &[]));
+        graph.add_pass(pass("resolve", &[HISTORY], &[COLOR]));
+        graph.add_pass(pass("blend", &[SURFACE], &[COLOR, HISTORY]));
+        assert_eq!(order(&graph), ["main", "blend", "resolve"]);
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let mut graph = graph();
+        graph.add_texture("other", TextureDesc::new(wgpu::TextureFormat::Rgba8Unorm));
+        graph.add_pass(pass("a", &["other"], &[COLOR]));
+        graph.add_pass(pass("b", &[COLOR], &["other"]));
+        let error = graph.compile().err().unwrap();
+        assert!(error.to_string().contains("cycle"), "{}", error);
+    }
+
+    #[test]
+    fn reading_and_writing_a_texture_is_an_error() {
+        let mut graph = graph();
+        graph.add_pass(pass("a", &[COLOR], &[COLOR]));
+        assert!(graph.compile().is_err());
+    }
+
+    #[test]
+    fn first_write_clears_and_later_writes_load() {
+        let mut graph = graph();
+        graph.add_pass(pass("main", &[COLOR], &[]));
+        graph.add_pass(pass("overlay", &[COLOR], &[]));
+        graph.add_pass(pass("present", &[SURFACE], &[COLOR]));
+
+        let compiled = graph.compile().unwrap();
+        let ops: Vec<_> = compiled.iter().map(|c| c.color_ops[0]).collect();
+        assert_eq!(ops[0].load, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+        assert_eq!(ops[1].load, wgpu::LoadOp::Load);
+        assert_eq!(ops[2].load, wgpu::LoadOp::Clear(wgpu::Color::BLACK));
+        // Stored while read later, and the surface and history textures always are.
+        assert!(ops[0].store && ops[1].store && ops[2].store);
+    }
+
+    #[test]
+    fn unread_targets_are_not_stored() {
+        let mut graph = graph();
+        graph.add_pass(pass("main", &[COLOR], &[]));
+        graph.add_pass(pass("resolve", &[HISTORY], &[]));
+        let compiled = graph.compile().unwrap();
+        assert!(!compiled[0].color_ops[0].store);
+        assert!(compiled[1].color_ops[0].store);
+    }
+
+    #[test]
+    fn generation_changes_when_textures_have_to_be_reallocated() {
+        let mut graph = graph();
+        let generation = graph.generation();
+
+        graph.resize(64, 64);
+        graph.add_texture(COLOR, TextureDesc::new(wgpu::TextureFormat::Rgba16Float));
+        assert_eq!(graph.generation(), generation);
+
+        graph.resize(128, 64);
+        assert!(graph.generation() > generation);
+
+        let generation = graph.generation();
+        graph.add_texture(COLOR, TextureDesc::new(wgpu::TextureFormat::Rgba8Unorm));
+        assert!(graph.generation() > generation);
+    }
+}
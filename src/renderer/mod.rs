@@ -1,37 +1,218 @@
-use anyhow::{Context, Ok, Result};
+//! The wgpu renderer, its render graph and the pipelines drawing the entities.
 
+use std::{
+    error::Error,
+    fmt,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail, Context, Ok, Result};
+use glam::Vec2;
+use log::{info, warn};
+
+use crate::entity;
+
+pub mod adapter;
 pub mod billboard;
+pub mod bloom;
+pub mod builder;
+pub mod cache;
 pub mod cube;
+pub mod fxaa;
+pub mod graph;
+pub mod overlay;
 pub mod particles;
+pub mod profiler;
+pub mod screenshot;
+pub mod taa;
+pub mod tonemap;
+pub mod uniform;
+pub mod view;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TargetConfig {
+    pub color_format: wgpu::TextureFormat,
+    pub depth_format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    /// Depth is 1 at the near plane and 0 at infinity.
+    pub reverse_z: bool,
+    /// Format of the second color target of the main pass, which gets screen space motion vectors
+    /// while TAA is enabled.
+    pub motion_vector_format: Option<wgpu::TextureFormat>,
+}
+
+impl TargetConfig {
+    /// The color targets of the main pass in order.
+    pub fn color_formats(&self) -> Vec<wgpu::TextureFormat> {
+        std::iter::once(self.color_format)
+            .chain(self.motion_vector_format)
+            .collect()
+    }
+
+    /// Depth test passing fragments at least as close to the camera as the stored depth.
+    pub fn depth_compare(&self) -> wgpu::CompareFunction {
+        if self.reverse_z {
+            wgpu::CompareFunction::GreaterEqual
+        } else {
+            wgpu::CompareFunction::LessEqual
+        }
+    }
+}
+
+/// Post-process anti-aliasing, applied on top of MSAA.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AntiAliasing {
+    None,
+    Fxaa,
+    /// Needs MSAA to be disabled.
+    Taa,
+}
+
+impl AntiAliasing {
+    pub const ALL: [AntiAliasing; 3] = [AntiAliasing::None, AntiAliasing::Fxaa, AntiAliasing::Taa];
+}
+
+pub const PRESENT_MODES: [wgpu::PresentMode; 3] = [
+    wgpu::PresentMode::Fifo,
+    wgpu::PresentMode::Mailbox,
+    wgpu::PresentMode::Immediate,
+];
+
+/// Returned from `Renderer::render` when the device has to be recreated with `Renderer::recover`.
+#[derive(Debug)]
+pub struct DeviceLost;
+
+impl fmt::Display for DeviceLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The GPU device was lost")
+    }
+}
+
+impl Error for DeviceLost {}
 
 pub struct Renderer {
+    adapter_options: adapter::AdapterOptions,
     surface: wgpu::Surface,
     surface_format: wgpu::TextureFormat,
+    present_mode: wgpu::PresentMode,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    depth_texture_view: wgpu::TextureView,
+    /// The first error wgpu reported outside of an error scope since the last frame.
+    device_error: Arc<Mutex<Option<wgpu::Error>>>,
+    graph: graph::RenderGraph,
+    minimized: bool,
+    /// Where to save the next frame presented.
+    screenshot: Option<PathBuf>,
+    sample_count: u32,
+    reverse_z: bool,
+    anti_aliasing: AntiAliasing,
+    frame_index: u64,
+    view: view::ViewBinding,
+    pipeline_cache: cache::PipelineCache,
+    profiler: profiler::Profiler,
+    bloom: bloom::PipelineState,
+    tonemap: tonemap::PipelineState,
+    fxaa: fxaa::PipelineState,
+    taa: taa::PipelineState,
+    overlay: overlay::PipelineState,
 }
 
 impl Renderer {
     const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    const HDR_COLOR: graph::ResourceId = "hdr_color";
+    const MSAA_COLOR: graph::ResourceId = "msaa_color";
+    const LDR_COLOR: graph::ResourceId = "ldr_color";
+    /// WebGPU guarantees 1 and 4 samples for render attachments of every format. wgpu can't tell
+    /// which other counts the HDR and depth formats support, so they aren't offered.
+    const SAMPLE_COUNTS: [u32; 2] = [1, 4];
 
-    pub async fn new(instance: &wgpu::Instance, window: &winit::window::Window) -> Result<Self> {
+    pub async fn new(
+        instance: &wgpu::Instance,
+        window: &winit::window::Window,
+        adapter_options: adapter::AdapterOptions,
+    ) -> Result<Self> {
         let surface = unsafe { instance.create_surface(window) };
+        let (adapter, device, queue) =
+            Self::request_device(instance, &surface, &adapter_options).await?;
+        let device_error = Self::catch_device_errors(&device);
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .context("No adapter found")?;
+        let winit::dpi::PhysicalSize { width, height } = window.inner_size();
+
+        let surface_format = surface
+            .get_preferred_format(&adapter)
+            .context("There is no preferred format")?;
+        let present_mode = wgpu::PresentMode::Fifo;
+        Self::configure_surface(
+            &surface,
+            &device,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+            surface_format,
+            present_mode,
+            width,
+            height,
+        );
+
+        let start = Instant::now();
+        let view = view::ViewBinding::new(&device);
+        let bloom = bloom::PipelineState::new(&device, Self::HDR_COLOR);
+        let tonemap =
+            tonemap::PipelineState::new(&device, Self::HDR_COLOR, bloom::OUTPUT, surface_format);
+        let fxaa = fxaa::PipelineState::new(&device, Self::LDR_COLOR, surface_format);
+        let taa = taa::PipelineState::new(&device, Self::HDR_COLOR);
+        let overlay = overlay::PipelineState::new(&device, &queue, surface_format);
+        info!(
+            "Built post-processing and overlay pipelines in {:.2} ms",
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+        let profiler = profiler::Profiler::new(&device, &queue);
 
+        let mut renderer = Self {
+            adapter_options,
+            surface,
+            surface_format,
+            present_mode,
+            device,
+            queue,
+            device_error,
+            graph: graph::RenderGraph::new(width, height),
+            minimized: width == 0 || height == 0,
+            screenshot: None,
+            sample_count: 4,
+            reverse_z: false,
+            anti_aliasing: AntiAliasing::None,
+            frame_index: 0,
+            view,
+            pipeline_cache: cache::PipelineCache::default(),
+            profiler,
+            bloom,
+            tonemap,
+            fxaa,
+            taa,
+            overlay,
+        };
+        renderer.declare_targets();
+        renderer.set_bloom_settings(bloom::Settings::default());
+
+        Ok(renderer)
+    }
+
+    async fn request_device(
+        instance: &wgpu::Instance,
+        surface: &wgpu::Surface,
+        adapter_options: &adapter::AdapterOptions,
+    ) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+        let adapter = adapter::request_adapter(instance, surface, adapter_options).await?;
+
+        // Timestamps are optional, the profiler falls back to CPU timings without them.
+        let features = adapter.features() & wgpu::Features::TIMESTAMP_QUERY;
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
+                    features,
                     limits: wgpu::Limits::default(),
                 },
                 None,
@@ -39,141 +220,543 @@ impl Renderer {
             .await
             .context("No device found")?;
 
-        let winit::dpi::PhysicalSize { width, height } = window.inner_size();
+        Ok((adapter, device, queue))
+    }
 
-        let surface_format = surface
+    /// wgpu panics on errors nobody handles, so keep them for `render` to report instead.
+    fn catch_device_errors(device: &wgpu::Device) -> Arc<Mutex<Option<wgpu::Error>>> {
+        let device_error = Arc::new(Mutex::new(None));
+        let handler_error = device_error.clone();
+        device.on_uncaptured_error(move |error| {
+            handler_error.lock().unwrap().get_or_insert(error);
+        });
+        device_error
+    }
+
+    /// Errors wgpu reported since the last call, `DeviceLost` if the device has to be recreated.
+    fn check_device(&self) -> Result<()> {
+        let error = match self.device_error.lock().unwrap().take() {
+            Some(error) => error,
+            None => return Ok(()),
+        };
+        // wgpu errors aren't `Sync`, so they can't be wrapped and are flattened into a message.
+        let messages: Vec<_> = std::iter::successors(Some(&error as &dyn Error), |&e| e.source())
+            .map(|e| e.to_string())
+            .collect();
+        let error = anyhow!(messages.join(": "));
+        // wgpu has no device lost callback yet, the loss only shows up in the errors it causes.
+        if messages.iter().any(|m| m.contains("device is lost")) {
+            Err(error.context(DeviceLost))
+        } else {
+            Err(error.context("wgpu error"))
+        }
+    }
+
+    /// Recreates the device and everything allocated from it after `DeviceLost`, keeping the
+    /// surface and the settings. Pipelines have to be rebuilt afterwards.
+    pub async fn recover(&mut self, instance: &wgpu::Instance) -> Result<()> {
+        let (adapter, device, queue) =
+            Self::request_device(instance, &self.surface, &self.adapter_options).await?;
+        self.device_error = Self::catch_device_errors(&device);
+        self.device = device;
+        self.queue = queue;
+
+        self.surface_format = self
+            .surface
             .get_preferred_format(&adapter)
             .context("There is no preferred format")?;
-        Self::configure_surface(&surface, &device, surface_format, width, height);
 
-        let depth_texture_view =
-            Self::create_depth_texture_view(&device, Self::DEPTH_FORMAT, width, height);
+        let (width, height) = self.graph.size();
+        self.graph = graph::RenderGraph::new(width, height);
+        if !self.minimized {
+            self.configure(width, height);
+        }
 
-        Ok(Self {
-            surface,
-            surface_format,
-            device,
-            queue,
-            depth_texture_view,
-        })
+        let bloom_settings = self.bloom.settings();
+        let tone_mapping = self.tonemap.tone_mapping();
+        let exposure = self.tonemap.exposure();
+        self.view = view::ViewBinding::new(&self.device);
+        self.pipeline_cache = cache::PipelineCache::default();
+        let profiling = self.profiler.enabled();
+        self.profiler = profiler::Profiler::new(&self.device, &self.queue);
+        self.profiler.set_enabled(profiling);
+        self.bloom = bloom::PipelineState::new(&self.device, Self::HDR_COLOR);
+        self.tonemap = tonemap::PipelineState::new(
+            &self.device,
+            Self::HDR_COLOR,
+            bloom::OUTPUT,
+            self.surface_format,
+        );
+        self.fxaa = fxaa::PipelineState::new(&self.device, Self::LDR_COLOR, self.surface_format);
+        self.taa = taa::PipelineState::new(&self.device, Self::HDR_COLOR);
+        self.overlay = overlay::PipelineState::new(&self.device, &self.queue, self.surface_format);
+
+        // Routes the post-processing inputs and declares the targets again.
+        self.set_anti_aliasing(self.anti_aliasing)?;
+        self.set_bloom_settings(bloom_settings);
+        self.set_tone_mapping(tone_mapping);
+        self.set_exposure(exposure);
+
+        Ok(())
+    }
+
+    fn declare_targets(&mut self) {
+        let sample_count = self.sample_count;
+        self.graph
+            .add_texture(Self::HDR_COLOR, graph::TextureDesc::new(Self::HDR_FORMAT));
+        self.graph.add_texture(
+            graph::DEPTH,
+            graph::TextureDesc {
+                sample_count,
+                ..graph::TextureDesc::new(Self::DEPTH_FORMAT)
+            },
+        );
+        if sample_count > 1 {
+            self.graph.add_texture(
+                Self::MSAA_COLOR,
+                graph::TextureDesc {
+                    sample_count,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    ..graph::TextureDesc::new(Self::HDR_FORMAT)
+                },
+            );
+        } else {
+            self.graph.remove_texture(Self::MSAA_COLOR);
+        }
+        if self.anti_aliasing == AntiAliasing::Fxaa {
+            self.graph.add_texture(
+                Self::LDR_COLOR,
+                graph::TextureDesc::new(self.surface_format),
+            );
+        } else {
+            self.graph.remove_texture(Self::LDR_COLOR);
+        }
     }
 
     fn configure_surface(
         surface: &wgpu::Surface,
         device: &wgpu::Device,
+        usage: wgpu::TextureUsages,
         format: wgpu::TextureFormat,
+        present_mode: wgpu::PresentMode,
         width: u32,
         height: u32,
     ) {
         surface.configure(
             device,
             &wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                usage,
                 format,
                 width,
                 height,
-                present_mode: wgpu::PresentMode::Fifo,
+                present_mode,
             },
         );
     }
 
-    fn create_depth_texture_view(
-        device: &wgpu::Device,
-        format: wgpu::TextureFormat,
-        width: u32,
-        height: u32,
-    ) -> wgpu::TextureView {
-        device
-            .create_texture(&wgpu::TextureDescriptor {
-                label: Some("Depth texture"),
-                size: wgpu::Extent3d {
-                    width,
-                    height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            })
-            .create_view(&wgpu::TextureViewDescriptor {
-                label: Some("Depth texture view"),
-                format: Some(Self::DEPTH_FORMAT),
-                dimension: Some(wgpu::TextureViewDimension::D2),
-                aspect: wgpu::TextureAspect::DepthOnly,
-                base_mip_level: 0,
-                mip_level_count: None,
-                base_array_layer: 0,
-                array_layer_count: None,
-            })
+    pub fn target_config(&self) -> TargetConfig {
+        TargetConfig {
+            color_format: Self::HDR_FORMAT,
+            depth_format: Self::DEPTH_FORMAT,
+            sample_count: self.sample_count,
+            reverse_z: self.reverse_z,
+            motion_vector_format: (self.anti_aliasing == AntiAliasing::Taa)
+                .then_some(taa::MOTION_FORMAT),
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn supported_sample_counts(&self) -> &[u32] {
+        &Self::SAMPLE_COUNTS
+    }
+
+    /// Pipelines have to be rebuilt with the new `target_config` afterwards.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<()> {
+        if !Self::SAMPLE_COUNTS.contains(&sample_count) {
+            bail!(
+                "Sample count {} is not supported, expected one of {:?}",
+                sample_count,
+                Self::SAMPLE_COUNTS
+            );
+        }
+        if sample_count > 1 && self.anti_aliasing == AntiAliasing::Taa {
+            bail!("TAA can't be combined with MSAA");
+        }
+        self.sample_count = sample_count;
+        self.declare_targets();
+        Ok(())
     }
 
-    pub fn surface_format(&self) -> wgpu::TextureFormat {
-        self.surface_format
+    pub fn reverse_z(&self) -> bool {
+        self.reverse_z
     }
 
-    pub fn depth_texture_format(&self) -> wgpu::TextureFormat {
-        Self::DEPTH_FORMAT
+    /// Pipelines have to be rebuilt with the new `target_config` and cameras have to project with
+    /// the matching depth range afterwards.
+    pub fn set_reverse_z(&mut self, reverse_z: bool) {
+        self.reverse_z = reverse_z;
+    }
+
+    pub fn anti_aliasing(&self) -> AntiAliasing {
+        self.anti_aliasing
+    }
+
+    pub fn set_anti_aliasing(&mut self, anti_aliasing: AntiAliasing) -> Result<()> {
+        if anti_aliasing == AntiAliasing::Taa && self.sample_count > 1 {
+            bail!("TAA can't be combined with MSAA");
+        }
+        if anti_aliasing == AntiAliasing::Taa && self.anti_aliasing != AntiAliasing::Taa {
+            self.taa.reset_history();
+        }
+        self.anti_aliasing = anti_aliasing;
+
+        let scene_color = if anti_aliasing == AntiAliasing::Taa {
+            taa::OUTPUT
+        } else {
+            Self::HDR_COLOR
+        };
+        self.bloom.set_input(scene_color);
+        self.tonemap.set_input(scene_color);
+        self.tonemap
+            .set_output(if anti_aliasing == AntiAliasing::Fxaa {
+                Self::LDR_COLOR
+            } else {
+                graph::SURFACE
+            });
+        self.declare_targets();
+        Ok(())
+    }
+
+    /// Projection offset the camera has to apply in the next frame.
+    pub fn jitter(&self) -> Vec2 {
+        if self.anti_aliasing != AntiAliasing::Taa {
+            return Vec2::ZERO;
+        }
+        let (width, height) = self.graph.size();
+        taa::jitter(self.frame_index, width, height)
     }
 
     pub fn device(&self) -> &wgpu::Device {
         &self.device
     }
 
-    pub fn render(&self, pipeline: &impl Pipeline) {
-        let frame_buffer = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to get next surface texture");
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// Camera bind group shared by all pipelines, updated at the start of every frame.
+    pub fn view(&self) -> &view::ViewBinding {
+        &self.view
+    }
+
+    /// Pipelines built for this device, emptied when it is recreated.
+    pub fn pipeline_cache(&self) -> &cache::PipelineCache {
+        &self.pipeline_cache
+    }
+
+    /// `time` is the simulated time in seconds, exposed to shaders. The pipelines are prepared for
+    /// the current targets first, see `Pipeline::prepare`.
+    pub fn render(
+        &mut self,
+        camera: &entity::Camera,
+        time: f32,
+        pipelines: &mut [&mut dyn Pipeline],
+    ) -> Result<()> {
+        self.check_device()?;
+        if self.minimized {
+            self.overlay.clear();
+            return Ok(());
+        }
+
+        let acquire = Instant::now();
+        let frame_buffer = match self.surface.get_current_texture() {
+            Result::Ok(frame_buffer) => frame_buffer,
+            Err(wgpu::SurfaceError::Timeout) => {
+                warn!("Timed out waiting for the next surface texture, skipping the frame");
+                self.overlay.clear();
+                return Ok(());
+            }
+            Err(e @ (wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost)) => {
+                warn!("Surface is {}, reconfiguring it", e);
+                let (width, height) = self.graph.size();
+                self.configure(width, height);
+                self.overlay.clear();
+                return Ok(());
+            }
+            Err(e) => return Err(e).context("Failed to get next surface texture"),
+        };
+
+        self.profiler.record("acquire", acquire);
+        let encode = Instant::now();
+        self.profiler.begin_gpu_frame();
 
         let frame_buffer_view = frame_buffer.texture.create_view(&Default::default());
 
         let mut encoder = self.device.create_command_encoder(&Default::default());
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &frame_buffer_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture_view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
-                    }),
-                    stencil_ops: None,
-                }),
+        let (width, height) = self.graph.size();
+        self.view.update(&self.queue, camera, time, width, height);
+
+        let mut color_attachments = vec![if self.sample_count > 1 {
+            graph::ColorAttachment {
+                target: Self::MSAA_COLOR,
+                resolve_target: Some(Self::HDR_COLOR),
+                clear: Some(wgpu::Color::BLACK),
+            }
+        } else {
+            graph::ColorAttachment {
+                target: Self::HDR_COLOR,
+                resolve_target: None,
+                clear: Some(wgpu::Color::BLACK),
+            }
+        }];
+        // Pixels no object wrote motion to are left transparent for TAA to reproject instead.
+        if self.anti_aliasing == AntiAliasing::Taa {
+            color_attachments.push(graph::ColorAttachment {
+                target: taa::MOTION,
+                resolve_target: None,
+                clear: Some(wgpu::Color::TRANSPARENT),
             });
+        }
+        self.graph.add_pass(graph::PassDesc {
+            name: graph::MAIN_PASS,
+            color_attachments,
+            depth_attachment: Some(graph::DepthAttachment {
+                target: graph::DEPTH,
+                clear: Some(if self.reverse_z { 0.0 } else { 1.0 }),
+            }),
+            reads: vec![],
+        });
+        let target_config = self.target_config();
+        for pipeline in pipelines.iter_mut() {
+            pipeline.prepare(
+                &self.device,
+                &self.pipeline_cache,
+                &target_config,
+                &self.view,
+            );
+        }
+        for pipeline in pipelines.iter() {
+            pipeline.setup(&mut self.graph);
+        }
+        self.bloom.setup(&mut self.graph);
+        self.tonemap.setup(&mut self.graph);
+        match self.anti_aliasing {
+            AntiAliasing::None => (),
+            AntiAliasing::Fxaa => self.fxaa.setup(&mut self.graph),
+            AntiAliasing::Taa => self.taa.setup(&mut self.graph),
+        }
+        self.overlay.setup(&mut self.graph);
 
-            pipeline.render(&mut render_pass);
+        self.graph.allocate(&self.device);
+        self.bloom.update(&self.device, &self.graph);
+        self.tonemap.update(&self.device, &self.graph);
+        match self.anti_aliasing {
+            AntiAliasing::None => (),
+            AntiAliasing::Fxaa => self.fxaa.update(&self.device, &self.graph),
+            AntiAliasing::Taa => self
+                .taa
+                .update(&self.device, &self.queue, &self.graph, camera),
         }
+        self.overlay
+            .update(&self.device, &self.queue, width, height);
+
+        let mut pipelines: Vec<&dyn Pipeline> = pipelines.iter().map(|p| &**p as _).collect();
+        pipelines.push(&self.bloom);
+        pipelines.push(&self.tonemap);
+        match self.anti_aliasing {
+            AntiAliasing::None => (),
+            AntiAliasing::Fxaa => pipelines.push(&self.fxaa),
+            AntiAliasing::Taa => pipelines.push(&self.taa),
+        }
+        pipelines.push(&self.overlay);
+
+        self.graph.execute(
+            &self.device,
+            &mut encoder,
+            &frame_buffer_view,
+            &pipelines,
+            &mut self.profiler,
+        )?;
+
+        let screenshot = match self.screenshot.take() {
+            Some(path) => Some((
+                screenshot::Screenshot::new(
+                    &self.device,
+                    &mut encoder,
+                    &frame_buffer.texture,
+                    self.surface_format,
+                    width,
+                    height,
+                )?,
+                path,
+            )),
+            None => None,
+        };
+
+        self.profiler.resolve(&mut encoder);
+        self.profiler.record("encode", encode);
 
+        let submit = Instant::now();
         self.queue.submit(Some(encoder.finish()));
+        self.profiler.record("submit", submit);
 
+        let present = Instant::now();
         frame_buffer.present();
+        self.profiler.record("present", present);
+
+        // The surface can only be reconfigured without the copy usage once the frame is presented.
+        if let Some((screenshot, path)) = screenshot {
+            screenshot.save(&self.device, &path)?;
+            info!("Saved screenshot to {:?}", path);
+            self.configure(width, height);
+        }
+        self.profiler.end_frame(&self.device, self.frame_index);
+        self.frame_index += 1;
+
+        self.check_device()
+    }
+
+    /// Timings of the recent frames, recorded while enabled.
+    pub fn profiler(&mut self) -> &mut profiler::Profiler {
+        &mut self.profiler
+    }
+
+    /// Text and shapes drawn over the next frame.
+    pub fn overlay(&mut self) -> &mut overlay::PipelineState {
+        &mut self.overlay
+    }
+
+    pub fn tone_mapping(&self) -> tonemap::ToneMapping {
+        self.tonemap.tone_mapping()
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: tonemap::ToneMapping) {
+        self.tonemap.set_tone_mapping(&self.queue, tone_mapping);
+    }
+
+    /// Exposure in EV stops.
+    pub fn exposure(&self) -> f32 {
+        self.tonemap.exposure()
+    }
+
+    pub fn set_exposure(&mut self, exposure: f32) {
+        self.tonemap.set_exposure(&self.queue, exposure);
+    }
+
+    pub fn bloom_settings(&self) -> bloom::Settings {
+        self.bloom.settings()
+    }
+
+    pub fn set_bloom_settings(&mut self, settings: bloom::Settings) {
+        self.bloom.set_settings(&self.queue, settings);
+        let intensity = if settings.enabled {
+            settings.intensity
+        } else {
+            0.
+        };
+        self.tonemap.set_bloom_intensity(&self.queue, intensity);
     }
 
+    /// Stops rendering while the window has no area, until it is resized again.
     pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
-        let Self {
-            surface,
-            device,
-            surface_format,
-            depth_texture_view,
-            ..
-        } = self;
-        *depth_texture_view =
-            Self::create_depth_texture_view(device, Self::DEPTH_FORMAT, size.width, size.height);
-        Self::configure_surface(surface, device, *surface_format, size.width, size.height);
+        self.minimized = size.width == 0 || size.height == 0;
+        if self.minimized {
+            return;
+        }
+        self.graph.resize(size.width, size.height);
+        self.configure(size.width, size.height);
+    }
+
+    /// Saves the next frame presented as a PPM image.
+    pub fn request_screenshot(&mut self, path: PathBuf) {
+        let configured = self.screenshot.is_some();
+        self.screenshot = Some(path);
+        if !configured && !self.minimized {
+            let (width, height) = self.graph.size();
+            self.configure(width, height);
+        }
+    }
+
+    /// Number of frames presented so far.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.present_mode
+    }
+
+    /// wgpu falls back to `Fifo`, which every surface supports, if the mode isn't available.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        self.present_mode = present_mode;
+        if !self.minimized {
+            let (width, height) = self.graph.size();
+            self.configure(width, height);
+        }
+    }
+
+    pub fn minimized(&self) -> bool {
+        self.minimized
+    }
+
+    fn configure(&self, width: u32, height: u32) {
+        // Copying from the surface isn't guaranteed to be supported, so only ask for it when needed.
+        let usage = if self.screenshot.is_some() {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        };
+        Self::configure_surface(
+            &self.surface,
+            &self.device,
+            usage,
+            self.surface_format,
+            self.present_mode,
+            width,
+            height,
+        );
     }
 }
 
 pub trait Pipeline {
-    fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>);
+    /// Recreates whatever was made for other targets, like render bundles recorded with another
+    /// sample count. Called at the start of every frame.
+    fn prepare(
+        &mut self,
+        _device: &wgpu::Device,
+        _pipeline_cache: &cache::PipelineCache,
+        _target_config: &TargetConfig,
+        _view: &view::ViewBinding,
+    ) {
+    }
+
+    /// Shown in profiles, the module of the implementing type by default.
+    fn name(&self) -> &'static str {
+        let path = std::any::type_name::<Self>();
+        path.rsplit("::").nth(1).unwrap_or(path)
+    }
+
+    /// Declares the textures and passes this pipeline needs and the passes it draws in, see
+    /// `RenderGraph::add_draw`. Pipelines draw in the main pass by default.
+    fn setup(&self, graph: &mut graph::RenderGraph) {
+        graph.add_draw(graph::MAIN_PASS, self.name());
+    }
+
+    /// Called for every pass the pipeline draws in this frame.
+    fn render<'a>(&'a self, pass: &str, render_pass: &mut wgpu::RenderPass<'a>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renderer_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Renderer>();
+    }
 }
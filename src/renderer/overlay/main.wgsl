@@ -0,0 +1,48 @@
+struct Uniforms {
+    screen_size: vec2<f32>;
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+@group(0) @binding(1)
+var font: texture_2d<f32>;
+
+let GLYPH_SIZE: vec2<f32> = vec2<f32>(5.0, 7.0);
+let SOLID: u32 = 0xffffffffu;
+
+struct VertexOut {
+    @builtin(position) position: vec4<f32>;
+    @location(0) uv: vec2<f32>;
+    @location(1) color: vec4<f32>;
+    @location(2) @interpolate(flat) glyph: u32;
+};
+
+@stage(vertex)
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) glyph: u32,
+) -> VertexOut {
+    // Two triangles covering the quad, from the top left corner.
+    let corner = vec2<f32>(f32((0x2cu >> vertex_index) & 1u), f32((0x32u >> vertex_index) & 1u));
+    let pixel = position + corner * size;
+
+    var out: VertexOut;
+    out.position = vec4<f32>(pixel / uniforms.screen_size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.uv = corner;
+    out.color = color;
+    out.glyph = glyph;
+    return out;
+}
+
+@stage(fragment)
+fn fs_main(vertex: VertexOut) -> @location(0) vec4<f32> {
+    if (vertex.glyph == SOLID) {
+        return vertex.color;
+    }
+    let texel = min(vec2<i32>(vertex.uv * GLYPH_SIZE), vec2<i32>(GLYPH_SIZE) - vec2<i32>(1));
+    let coverage = textureLoad(font, vec2<i32>(i32(vertex.glyph) * 5 + texel.x, texel.y), 0).r;
+    return vec4<f32>(vertex.color.rgb, vertex.color.a * coverage);
+}
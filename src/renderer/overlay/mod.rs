@@ -0,0 +1,375 @@
+use std::mem::size_of;
+
+use bytemuck::{cast_slice, Pod, Zeroable};
+use glam::{vec2, Vec2, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::renderer::{self, graph, uniform::UniformBuffer};
+
+pub const PASS: &str = "overlay";
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+/// Glyph index drawing a filled rectangle instead of a character.
+const SOLID: u32 = u32::MAX;
+
+/// 5x7 glyphs for `' '` to `` '`' ``, one byte per row with the leftmost pixel in bit 4.
+#[rustfmt::skip]
+const FONT: [[u8; 7]; 65] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04], // !
+    [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], // "
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], // #
+    [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], // $
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // %
+    [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], // &
+    [0x0c, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // '
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // (
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // )
+    [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], // *
+    [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], // ,
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], // .
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // /
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], // 0
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], // 1
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], // 2
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], // 3
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], // 4
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], // 5
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], // 6
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // 7
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], // 8
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], // 9
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], // :
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], // ;
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // <
+    [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], // =
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // >
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // ?
+    [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], // @
+    [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11], // A
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], // B
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], // C
+    [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], // D
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], // E
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], // F
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], // G
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // H
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // I
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], // J
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // K
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], // L
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], // M
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // N
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // O
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], // P
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], // Q
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], // R
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], // S
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // T
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // U
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], // V
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], // W
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], // X
+    [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], // Y
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], // Z
+    [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], // [
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // \
+    [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], // ]
+    [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], // _
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], // `
+];
+
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+#[repr(C)]
+struct Uniforms {
+    screen_size: Vec2,
+    _pad0: [u8; 8],
+}
+
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+#[repr(C)]
+struct Instance {
+    position: Vec2,
+    size: Vec2,
+    color: Vec4,
+    glyph: u32,
+    _pad0: [u8; 12],
+}
+
+/// Text and rectangles drawn on top of the final image, in physical pixels from the top left
+/// corner. Everything queued is drawn in the next frame only.
+pub struct PipelineState {
+    uniform_buffer: UniformBuffer<Uniforms>,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    instance_buffer: wgpu::Buffer,
+    instance_capacity: usize,
+    instance_count: u32,
+    instances: Vec<Instance>,
+}
+
+impl PipelineState {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "Overlay uniforms", &Uniforms::default());
+        let font_view = Self::make_font_texture(device, queue).create_view(&Default::default());
+        let bind_group_layout = Self::make_bind_group_layout(device);
+        let bind_group =
+            Self::make_bind_group(device, &bind_group_layout, &uniform_buffer, &font_view);
+        let render_pipeline = Self::make_render_pipeline(device, &bind_group_layout, output_format);
+        let instance_capacity = 256;
+        let instance_buffer = Self::make_instance_buffer(device, instance_capacity);
+
+        Self {
+            uniform_buffer,
+            bind_group,
+            render_pipeline,
+            instance_buffer,
+            instance_capacity,
+            instance_count: 0,
+            instances: Vec::new(),
+        }
+    }
+
+    /// All glyphs side by side in a single row.
+    fn make_font_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+        let width = GLYPH_WIDTH * FONT.len() as u32;
+        let mut pixels = vec![0u8; (width * GLYPH_HEIGHT) as usize];
+        for (index, glyph) in FONT.iter().enumerate() {
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if row & (0x10 >> x) != 0 {
+                        let column = index as u32 * GLYPH_WIDTH + x;
+                        pixels[y * width as usize + column as usize] = 0xff;
+                    }
+                }
+            }
+        }
+
+        device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Overlay font"),
+                size: wgpu::Extent3d {
+                    width,
+                    height: GLYPH_HEIGHT,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            &pixels,
+        )
+    }
+
+    fn make_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: UniformBuffer::<Uniforms>::binding_size(),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn make_bind_group(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &UniformBuffer<Uniforms>,
+        font_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(font_view),
+                },
+            ],
+        })
+    }
+
+    fn make_render_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(&wgpu::include_wgsl!("main.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Overlay"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: size_of::<Instance>() as _,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x2,
+                        1 => Float32x2,
+                        2 => Float32x4,
+                        3 => Uint32,
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: output_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    fn make_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Overlay instances"),
+            size: (capacity * size_of::<Instance>()) as _,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Size of `text` drawn with `scale` pixels per font pixel.
+    pub fn text_size(text: &str, scale: f32) -> Vec2 {
+        let columns = text.lines().map(|line| line.chars().count()).max();
+        let rows = text.lines().count();
+        vec2(
+            columns.unwrap_or(0) as f32 * (GLYPH_WIDTH + 1) as f32,
+            rows as f32 * (GLYPH_HEIGHT + 2) as f32,
+        ) * scale
+    }
+
+    /// Queues `text` with its top left corner at `position`, drawn with `scale` pixels per font
+    /// pixel. Lowercase letters are drawn as capitals and unknown characters as `?`.
+    pub fn text(&mut self, position: Vec2, scale: f32, color: Vec4, text: &str) {
+        let advance = vec2((GLYPH_WIDTH + 1) as f32, (GLYPH_HEIGHT + 2) as f32) * scale;
+        let size = vec2(GLYPH_WIDTH as f32, GLYPH_HEIGHT as f32) * scale;
+        for (row, line) in text.lines().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                let index = (c.to_ascii_uppercase() as u32)
+                    .checked_sub(' ' as u32)
+                    .filter(|&index| index < FONT.len() as u32)
+                    .unwrap_or('?' as u32 - ' ' as u32);
+                self.instances.push(Instance {
+                    position: position + vec2(column as f32, row as f32) * advance,
+                    size,
+                    color,
+                    glyph: index,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    /// Queues a filled rectangle, drawn in order with the text.
+    pub fn rect(&mut self, position: Vec2, size: Vec2, color: Vec4) {
+        self.instances.push(Instance {
+            position,
+            size,
+            color,
+            glyph: SOLID,
+            ..Default::default()
+        });
+    }
+
+    /// Drops everything queued, for frames that are skipped.
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    /// Uploads what was queued for this frame.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.uniform_buffer.write(
+            queue,
+            &Uniforms {
+                screen_size: vec2(width as f32, height as f32),
+                ..Default::default()
+            },
+        );
+
+        if self.instances.len() > self.instance_capacity {
+            self.instance_capacity = self.instances.len().next_power_of_two();
+            self.instance_buffer = Self::make_instance_buffer(device, self.instance_capacity);
+        }
+        queue.write_buffer(&self.instance_buffer, 0, cast_slice(&self.instances));
+        self.instance_count = self.instances.len() as _;
+        self.instances.clear();
+    }
+}
+
+impl renderer::Pipeline for PipelineState {
+    fn setup(&self, graph: &mut graph::RenderGraph) {
+        graph.add_pass(graph::PassDesc {
+            name: PASS,
+            color_attachments: vec![graph::ColorAttachment {
+                target: graph::SURFACE,
+                resolve_target: None,
+                clear: None,
+            }],
+            depth_attachment: None,
+            reads: vec![],
+        });
+        graph.add_draw(PASS, self.name());
+    }
+
+    fn render<'a>(&'a self, pass: &str, render_pass: &mut wgpu::RenderPass<'a>) {
+        if pass != PASS || self.instance_count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.instance_count);
+    }
+}
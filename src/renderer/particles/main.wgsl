@@ -1,6 +1,6 @@
 struct Uniforms {
-    mv_mat: mat4x4<f32>;
-    p_mat: mat4x4<f32>;
+    m_mat: mat4x4<f32>;
+    previous_m_mat: mat4x4<f32>;
     particle_size: f32;
 };
 
@@ -9,14 +9,16 @@ struct Instance {
     color: vec3<f32>;
 };
 
-@group(0) @binding(0)
+@group(1) @binding(0)
 var<uniform> uniforms: Uniforms;
-@group(0) @binding(1)
+@group(1) @binding(1)
 var<storage, read> instances: array<Instance>;
 
 struct VertexOut {
     @builtin(position) position: vec4<f32>;
     @location(0) color: vec4<f32>;
+    @location(1) clip_position: vec4<f32>;
+    @location(2) previous_clip_position: vec4<f32>;
 };
 
 @stage(vertex)
@@ -25,20 +27,33 @@ fn vs_main(
     @builtin(instance_index) instance_index: u32,
 ) -> VertexOut {
     let instance = instances[instance_index];
+    let offset = vec4<f32>(vertex_position * uniforms.particle_size, 0.0);
 
-    var position = uniforms.mv_mat * vec4<f32>(instance.position, 1.0);
-    position += vec4<f32>(vertex_position * uniforms.particle_size, 1.0);
+    var position = view.view * uniforms.m_mat * vec4<f32>(instance.position, 1.0);
+    position += offset;
+    let previous_position = view.previous_view * uniforms.previous_m_mat
+        * vec4<f32>(instance.position, 1.0) + offset;
 
     var out: VertexOut;
-    out.position = uniforms.p_mat * position;
+    out.position = view.projection * position;
     out.color = vec4<f32>(instance.color, 1.0);
+    out.clip_position = out.position;
+    out.previous_clip_position = view.previous_projection * previous_position;
 
     return out;
 }
 
 @stage(fragment)
 fn fs_main(
-  @location(0) color: vec4<f32>
+  vertex: VertexOut
 ) -> @location(0) vec4<f32> {
-    return color;
+    return vertex.color;
+}
+
+@stage(fragment)
+fn fs_motion(
+  vertex: VertexOut
+) -> MotionOut {
+    let motion = motion_vector(vertex.clip_position, vertex.previous_clip_position);
+    return MotionOut(vertex.color, motion);
 }
@@ -1,21 +1,27 @@
-use std::{mem::size_of, time::SystemTime};
+use std::time::SystemTime;
 
-use anyhow::Result;
 use bytemuck::{bytes_of, cast_slice, Pod, Zeroable};
 use glam::{const_vec3, vec3, Mat4, Vec3};
 use log::{debug, info};
-use pollster::FutureExt;
 use rand::prelude::*;
 use rand_pcg::Pcg64Mcg;
 use wgpu::util::DeviceExt;
 
-use crate::{entity, renderer};
+use crate::{
+    entity, renderer,
+    renderer::{
+        builder::{self, PipelineBuilder, RecordedBundle},
+        cache::PipelineCache,
+        uniform::UniformBuffer,
+        view,
+    },
+};
 
 #[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
 #[repr(C)]
 struct Uniforms {
-    mv_mat: Mat4,
-    p_mat: Mat4,
+    m_mat: Mat4,
+    previous_m_mat: Mat4,
     particle_size: f32,
     _pad0: [u8; 12],
 }
@@ -23,22 +29,9 @@ struct Uniforms {
 impl Uniforms {
     fn new(scene: &entity::Scene) -> Self {
         let entity::Scene {
-            camera,
-            particle_system,
-            ..
+            particle_system, ..
         } = scene;
 
-        let p_mat = {
-            let fovy = camera.fov / camera.aspect_ratio / 180.;
-            Mat4::perspective_lh(fovy, camera.aspect_ratio, camera.near, camera.far)
-        };
-
-        let v_mat = {
-            let center = camera.transform.position + camera.transform.rotation * Vec3::Z;
-            let up = Vec3::Y;
-            Mat4::look_at_lh(camera.transform.position, center, up)
-        };
-
         let m_mat = Mat4::from_scale_rotation_translation(
             particle_system.transform.scale,
             particle_system.transform.rotation,
@@ -46,8 +39,8 @@ impl Uniforms {
         );
 
         Self {
-            mv_mat: v_mat * m_mat,
-            p_mat,
+            m_mat,
+            previous_m_mat: m_mat,
             particle_size: particle_system.particle_size,
             ..Default::default()
         }
@@ -64,8 +57,17 @@ struct Instance {
 }
 
 pub struct PipelineState {
-    uniform_buffer: wgpu::Buffer,
-    render_bundle: wgpu::RenderBundle,
+    uniform_buffer: UniformBuffer<Uniforms>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    /// Particles in `instance_buffer`.
+    instance_count: u32,
+    /// The particle system of the last update.
+    particle_system: entity::ParticleSystem,
+    render_bundle: RecordedBundle<u32>,
+    /// Model matrix of the last update, for motion vectors.
+    previous_m_mat: Option<Mat4>,
 }
 
 impl PipelineState {
@@ -79,73 +81,56 @@ impl PipelineState {
 
     pub fn new(
         device: &wgpu::Device,
-        render_target_color_format: wgpu::TextureFormat,
-        render_target_depth_format: wgpu::TextureFormat,
+        pipeline_cache: &PipelineCache,
+        target_config: &renderer::TargetConfig,
+        view: &view::ViewBinding,
         scene: &entity::Scene,
     ) -> Self {
-        let uniform_buffer = Self::make_uniform_buffer(device, scene);
-        let vertex_buffer = Self::make_vertex_buffer(device);
-        let index_buffer = Self::make_index_buffer(device);
-        let instance_buffer = Self::make_instance_buffer(device, scene);
-
-        let bind_group_layout = Self::make_bind_group_layout(device);
-        let bind_group = Self::make_bind_group(
-            device,
-            &bind_group_layout,
-            &uniform_buffer,
-            &instance_buffer,
-        );
-        let render_pipeline = Self::make_render_pipeline(
-            device,
-            &bind_group_layout,
-            render_target_color_format,
-            render_target_depth_format,
-        );
-
-        let render_bundle = Self::make_render_bundle(
-            device,
-            render_target_color_format,
-            render_target_depth_format,
-            &render_pipeline,
-            &bind_group,
-            &vertex_buffer,
-            &index_buffer,
-            scene,
-        );
-
-        Self {
-            uniform_buffer,
-            render_bundle,
-        }
-    }
-
-    fn make_vertex_buffer(device: &wgpu::Device) -> wgpu::Buffer {
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        let uniform_buffer = UniformBuffer::new(device, "Uniform buffer", &Uniforms::new(scene));
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex buffer"),
             contents: bytes_of(&Self::PARTICLE_VERTICES),
             usage: wgpu::BufferUsages::VERTEX,
-        })
-    }
-
-    fn make_index_buffer(device: &wgpu::Device) -> wgpu::Buffer {
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index buffer"),
             contents: bytes_of(&Self::PARTICLE_INDICES),
             usage: wgpu::BufferUsages::INDEX,
-        })
-    }
+        });
+        let instance_buffer = Self::make_instance_buffer(device, &scene.particle_system);
 
-    fn make_instance_buffer(device: &wgpu::Device, scene: &entity::Scene) -> wgpu::Buffer {
-        let entity::Scene {
-            particle_system, ..
-        } = scene;
+        let mut pipeline_state = Self {
+            uniform_buffer,
+            vertex_buffer,
+            index_buffer,
+            instance_buffer,
+            instance_count: scene.particle_system.max_count,
+            particle_system: scene.particle_system,
+            render_bundle: RecordedBundle::default(),
+            previous_m_mat: None,
+        };
+        renderer::Pipeline::prepare(
+            &mut pipeline_state,
+            device,
+            pipeline_cache,
+            target_config,
+            view,
+        );
+        pipeline_state
+    }
 
-        let unix_milli = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as _;
-        info!("Seeded RNG with {}", unix_milli);
-        let mut rng = Pcg64Mcg::seed_from_u64(unix_milli);
+    fn make_instance_buffer(
+        device: &wgpu::Device,
+        particle_system: &entity::ParticleSystem,
+    ) -> wgpu::Buffer {
+        let seed = particle_system.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis() as _
+        });
+        info!("Seeded RNG with {}", seed);
+        let mut rng = Pcg64Mcg::seed_from_u64(seed);
 
         let instances: Vec<_> = (0..particle_system.max_count)
             .map(|_| Instance {
@@ -170,192 +155,71 @@ impl PipelineState {
         })
     }
 
-    fn make_uniform_buffer(device: &wgpu::Device, scene: &entity::Scene) -> wgpu::Buffer {
-        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform buffer"),
-            contents: bytes_of(&Uniforms::new(scene)),
-            usage: wgpu::BufferUsages::UNIFORM
-                | wgpu::BufferUsages::MAP_READ
-                | wgpu::BufferUsages::MAP_WRITE,
-        })
-    }
-
-    fn make_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(size_of::<Uniforms>() as _),
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: wgpu::BufferSize::new(size_of::<Instance>() as _),
-                    },
-                    count: None,
-                },
-            ],
-        })
-    }
-
-    fn make_bind_group(
-        device: &wgpu::Device,
-        bind_group_layout: &wgpu::BindGroupLayout,
-        uniform_buffer: &wgpu::Buffer,
-        instance_buffer: &wgpu::Buffer,
-    ) -> wgpu::BindGroup {
-        device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: instance_buffer.as_entire_binding(),
-                },
-            ],
-        })
-    }
-
-    fn make_render_pipeline(
-        device: &wgpu::Device,
-        bind_group_layout: &wgpu::BindGroupLayout,
-        render_target_color_format: wgpu::TextureFormat,
-        render_target_depth_format: wgpu::TextureFormat,
-    ) -> wgpu::RenderPipeline {
-        let shader_module = device.create_shader_module(&wgpu::include_wgsl!("main.wgsl"));
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[bind_group_layout],
-            push_constant_ranges: &[],
-        });
-
-        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "vs_main",
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: size_of::<Vec3>() as _,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &[wgpu::VertexAttribute {
-                        format: wgpu::VertexFormat::Float32x3,
-                        offset: 0,
-                        shader_location: 0,
-                    }],
-                }],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "fs_main",
-                targets: &[render_target_color_format.into()],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: render_target_depth_format,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::LessEqual,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState {
-                    constant: 0,
-                    slope_scale: 0.0,
-                    clamp: 0.0,
-                },
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        })
-    }
-
     fn make_render_bundle(
+        &self,
         device: &wgpu::Device,
-        render_target_color_format: wgpu::TextureFormat,
-        render_target_depth_format: wgpu::TextureFormat,
-        render_pipeline: &wgpu::RenderPipeline,
-        bind_group: &wgpu::BindGroup,
-        vertex_buffer: &wgpu::Buffer,
-        index_buffer: &wgpu::Buffer,
-        scene: &entity::Scene,
+        pipeline_cache: &PipelineCache,
+        target_config: &renderer::TargetConfig,
+        view: &view::ViewBinding,
     ) -> wgpu::RenderBundle {
-        let mut encoder =
-            device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
-                label: None,
-                color_formats: &[render_target_color_format],
-                depth_stencil: Some(wgpu::RenderBundleDepthStencil {
-                    format: render_target_depth_format,
-                    depth_read_only: false,
-                    stencil_read_only: true,
-                }),
-                sample_count: 1,
-                multiview: None,
-            });
-
-        encoder.set_pipeline(render_pipeline);
-        encoder.set_bind_group(0, bind_group, &[]);
-        encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
-        encoder.set_index_buffer(index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        encoder.draw_indexed(
-            0..(Self::PARTICLE_INDICES.len() as _),
-            0,
-            0..scene.particle_system.max_count,
-        );
-
-        encoder.finish(&wgpu::RenderBundleDescriptor { label: None })
+        PipelineBuilder::new("Particles", include_str!("main.wgsl"))
+            .vertex_buffer(builder::POSITIONS, &self.vertex_buffer)
+            .index_buffer(
+                &self.index_buffer,
+                wgpu::IndexFormat::Uint16,
+                0..Self::PARTICLE_INDICES.len() as _,
+            )
+            .uniform(0, wgpu::ShaderStages::VERTEX, &self.uniform_buffer)
+            .storage::<Instance>(1, wgpu::ShaderStages::VERTEX, &self.instance_buffer)
+            .instances(0..self.instance_count)
+            .build(device, pipeline_cache, target_config, view)
     }
 
-    pub fn update(&self, device: &wgpu::Device, scene: &entity::Scene) -> Result<()> {
-        let uniforms = Uniforms::new(scene);
+    /// Called once per frame, the previous call's scene is where motion vectors start from. A
+    /// changed particle count is applied in the next `prepare`.
+    pub fn update(&mut self, queue: &wgpu::Queue, scene: &entity::Scene) {
+        let mut uniforms = Uniforms::new(scene);
+        if let Some(previous_m_mat) = self.previous_m_mat {
+            uniforms.previous_m_mat = previous_m_mat;
+        }
         debug!("{:#?}", uniforms);
 
-        let uniform_buffer_slice = self.uniform_buffer.slice(..);
-        uniform_buffer_slice.map_blocking(device, wgpu::MapMode::Write)?;
-        uniform_buffer_slice
-            .get_mapped_range_mut()
-            .copy_from_slice(bytes_of(&uniforms));
-        self.uniform_buffer.unmap();
-
-        Ok(())
+        self.uniform_buffer.write(queue, &uniforms);
+        self.previous_m_mat = Some(uniforms.m_mat);
+        self.particle_system = scene.particle_system;
     }
 }
 
 impl renderer::Pipeline for PipelineState {
-    fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
-        render_pass.execute_bundles(Some(&self.render_bundle));
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        pipeline_cache: &PipelineCache,
+        target_config: &renderer::TargetConfig,
+        view: &view::ViewBinding,
+    ) {
+        if self.instance_count != self.particle_system.max_count {
+            self.instance_buffer = Self::make_instance_buffer(device, &self.particle_system);
+            self.instance_count = self.particle_system.max_count;
+        }
+        let instance_count = self.instance_count;
+        if !self
+            .render_bundle
+            .is_current(target_config, &instance_count)
+        {
+            let render_bundle =
+                self.make_render_bundle(device, pipeline_cache, target_config, view);
+            self.render_bundle
+                .set(render_bundle, target_config, instance_count);
+        }
     }
-}
 
-trait BufferSliceExt {
-    fn map_blocking(&self, device: &wgpu::Device, mode: wgpu::MapMode) -> Result<()>;
-}
-
-impl<'a> BufferSliceExt for wgpu::BufferSlice<'a> {
-    fn map_blocking(&self, device: &wgpu::Device, mode: wgpu::MapMode) -> Result<()> {
-        let fut = self.map_async(mode);
-        device.poll(wgpu::Maintain::Wait);
-        fut.block_on()?;
-        Ok(())
+    fn render<'a>(&'a self, pass: &str, render_pass: &mut wgpu::RenderPass<'a>) {
+        if pass != renderer::graph::MAIN_PASS {
+            return;
+        }
+        if let Some(render_bundle) = self.render_bundle.bundle() {
+            render_pass.execute_bundles(Some(render_bundle));
+        }
     }
 }
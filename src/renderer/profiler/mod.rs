@@ -0,0 +1,475 @@
+//! CPU and GPU timings of the recent frames, for the on-screen HUD and Chrome traces.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs,
+    future::Future,
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{self, Poll, Wake, Waker},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use bytemuck::cast_slice;
+use log::{info, warn};
+
+/// Frames kept for the averages and traces.
+const HISTORY: usize = 240;
+/// Timestamp queries available to a frame, two per GPU scope.
+const MAX_QUERIES: u32 = 1024;
+/// Frames whose GPU timings can be read back at once. Frames starting while all of them are in
+/// flight go without GPU timings.
+const READBACKS: usize = 3;
+
+/// A named span of time, in milliseconds since the profiler was created.
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub name: String,
+    pub start: f64,
+    pub duration: f64,
+    /// Number of scopes enclosing this one.
+    pub depth: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameProfile {
+    pub index: u64,
+    pub start: f64,
+    /// Time until the next frame started.
+    pub duration: f64,
+    pub cpu: Vec<Scope>,
+    /// Passes and the pipelines drawing in them, filled in once the timestamps were read back.
+    /// The GPU clock isn't related to the CPU's, so they start at the submission of the frame.
+    pub gpu: Vec<Scope>,
+}
+
+/// Average duration of the scopes with a name over the recent frames.
+#[derive(Debug, Clone)]
+pub struct ScopeAverage {
+    pub name: String,
+    pub depth: u32,
+    pub duration: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    pub frame: f64,
+    pub cpu: Vec<ScopeAverage>,
+    pub gpu: Vec<ScopeAverage>,
+}
+
+/// Command encoders and render passes, which timestamps can be written into.
+pub trait TimestampWriter {
+    fn write_timestamp(&mut self, query_set: &wgpu::QuerySet, query_index: u32);
+}
+
+impl TimestampWriter for wgpu::CommandEncoder {
+    fn write_timestamp(&mut self, query_set: &wgpu::QuerySet, query_index: u32) {
+        wgpu::CommandEncoder::write_timestamp(self, query_set, query_index);
+    }
+}
+
+impl TimestampWriter for wgpu::RenderPass<'_> {
+    fn write_timestamp(&mut self, query_set: &wgpu::QuerySet, query_index: u32) {
+        wgpu::RenderPass::write_timestamp(self, query_set, query_index);
+    }
+}
+
+/// Only behind a mutex to keep the profiler `Sync`, it is polled through `Mutex::get_mut`.
+type Mapping = Mutex<Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>> + Send>>>;
+
+/// Timestamps of a submitted frame, waiting for the GPU to finish it.
+struct Pending {
+    frame_index: u64,
+    /// Name and depth of each scope.
+    scopes: Vec<(&'static str, u32)>,
+    origin: f64,
+    size: wgpu::BufferAddress,
+    mapping: Mapping,
+}
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    pending: Option<Pending>,
+}
+
+/// Scopes of the frame being encoded, recorded into one of the readbacks.
+struct GpuFrame {
+    readback: usize,
+    scopes: Vec<(&'static str, u32)>,
+    /// Indices of the scopes begun but not ended yet, `None` for scopes without queries left.
+    open: Vec<Option<u32>>,
+    origin: f64,
+}
+
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    /// Nanoseconds per timestamp tick.
+    period: f64,
+    readbacks: Vec<Readback>,
+    frame: Option<GpuFrame>,
+}
+
+impl GpuTimer {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_QUERIES,
+        });
+        let readbacks = (0..READBACKS)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Timestamp readback buffer"),
+                    size: MAX_QUERIES as wgpu::BufferAddress * 8,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                pending: None,
+            })
+            .collect();
+
+        Some(Self {
+            query_set,
+            period: queue.get_timestamp_period() as f64,
+            readbacks,
+            frame: None,
+        })
+    }
+
+    /// Reads the timestamps of the frames the GPU finished, as `(frame index, scopes)`.
+    fn poll(&mut self, device: &wgpu::Device) -> Vec<(u64, Vec<Scope>)> {
+        device.poll(wgpu::Maintain::Poll);
+
+        let waker = Waker::from(Arc::new(NoopWake));
+        let mut context = task::Context::from_waker(&waker);
+        let mut finished = Vec::new();
+        for readback in &mut self.readbacks {
+            let ready = match &mut readback.pending {
+                Some(pending) => pending
+                    .mapping
+                    .get_mut()
+                    .unwrap()
+                    .as_mut()
+                    .poll(&mut context),
+                None => continue,
+            };
+            let result = match ready {
+                Poll::Ready(result) => result,
+                Poll::Pending => continue,
+            };
+            let pending = readback.pending.take().unwrap();
+            if result.is_err() {
+                continue;
+            }
+
+            let slice = readback.buffer.slice(..pending.size);
+            let scopes = {
+                let data = slice.get_mapped_range();
+                let ticks: &[u64] = cast_slice(&data);
+                let first = ticks.iter().step_by(2).copied().min().unwrap_or_default();
+                let millis = |ticks: u64| ticks as f64 * self.period / 1e6;
+                pending
+                    .scopes
+                    .into_iter()
+                    .zip(ticks.chunks_exact(2))
+                    .map(|((name, depth), ticks)| Scope {
+                        name: name.to_owned(),
+                        start: pending.origin + millis(ticks[0].saturating_sub(first)),
+                        duration: millis(ticks[1] - ticks[0]),
+                        depth,
+                    })
+                    .collect()
+            };
+            readback.buffer.unmap();
+            finished.push((pending.frame_index, scopes));
+        }
+        finished
+    }
+}
+
+struct NoopWake;
+
+impl Wake for NoopWake {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Collects the timings while enabled. CPU scopes are recorded by whoever does the work, GPU
+/// scopes are written by the render graph when the device supports timestamp queries.
+pub struct Profiler {
+    enabled: bool,
+    start: Instant,
+    frame: FrameProfile,
+    history: VecDeque<FrameProfile>,
+    gpu: Option<GpuTimer>,
+    /// Whether a GPU scope went without queries already, so that it is only reported once.
+    out_of_queries: bool,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let gpu = GpuTimer::new(device, queue);
+        if gpu.is_none() {
+            info!("Timestamp queries are not supported, only CPU timings will be profiled");
+        }
+
+        Self {
+            enabled: false,
+            start: Instant::now(),
+            frame: FrameProfile::default(),
+            history: VecDeque::new(),
+            gpu,
+            out_of_queries: false,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if enabled && !self.enabled {
+            self.frame = FrameProfile {
+                start: self.millis(Instant::now()),
+                ..Default::default()
+            };
+        }
+        self.enabled = enabled;
+    }
+
+    /// Whether GPU scopes are recorded at all.
+    pub fn gpu_supported(&self) -> bool {
+        self.gpu.is_some()
+    }
+
+    fn millis(&self, instant: Instant) -> f64 {
+        instant.saturating_duration_since(self.start).as_secs_f64() * 1000.
+    }
+
+    /// Records a CPU scope from `start` until now.
+    pub fn record(&mut self, name: &str, start: Instant) {
+        if !self.enabled {
+            return;
+        }
+        let scope = Scope {
+            name: name.to_owned(),
+            start: self.millis(start),
+            duration: start.elapsed().as_secs_f64() * 1000.,
+            depth: 0,
+        };
+        self.frame.cpu.push(scope);
+    }
+
+    /// Starts recording GPU scopes for the frame about to be encoded.
+    pub fn begin_gpu_frame(&mut self) {
+        let gpu = match &mut self.gpu {
+            Some(gpu) if self.enabled => gpu,
+            _ => return,
+        };
+        gpu.frame = gpu
+            .readbacks
+            .iter()
+            .position(|readback| readback.pending.is_none())
+            .map(|readback| GpuFrame {
+                readback,
+                scopes: Vec::new(),
+                open: Vec::new(),
+                origin: 0.,
+            });
+    }
+
+    /// Starts a GPU scope, ended by the next `end_gpu_scope`.
+    pub fn begin_gpu_scope(&mut self, writer: &mut impl TimestampWriter, name: &'static str) {
+        let (query_set, frame) = match &mut self.gpu {
+            Some(GpuTimer {
+                query_set,
+                frame: Some(frame),
+                ..
+            }) => (query_set, frame),
+            _ => return,
+        };
+        let index = frame.scopes.len() as u32;
+        if (index + 1) * 2 > MAX_QUERIES {
+            if !self.out_of_queries {
+                warn!(
+                    "Out of timestamp queries at GPU scope {:?}, it and later scopes of the frame \
+                     aren't timed",
+                    name
+                );
+                self.out_of_queries = true;
+            }
+            frame.open.push(None);
+            return;
+        }
+        let depth = frame.open.len() as u32;
+        frame.scopes.push((name, depth));
+        frame.open.push(Some(index));
+        writer.write_timestamp(query_set, index * 2);
+    }
+
+    pub fn end_gpu_scope(&mut self, writer: &mut impl TimestampWriter) {
+        let (query_set, frame) = match &mut self.gpu {
+            Some(GpuTimer {
+                query_set,
+                frame: Some(frame),
+                ..
+            }) => (query_set, frame),
+            _ => return,
+        };
+        if let Some(Some(index)) = frame.open.pop() {
+            writer.write_timestamp(query_set, index * 2 + 1);
+        }
+    }
+
+    /// Resolves the timestamps of the frame into its readback buffer, right before submitting it.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let origin = self.millis(Instant::now());
+        let gpu = match &mut self.gpu {
+            Some(gpu) => gpu,
+            None => return,
+        };
+        let frame = match &mut gpu.frame {
+            Some(frame) if !frame.scopes.is_empty() => frame,
+            _ => return,
+        };
+        frame.origin = origin;
+
+        encoder.resolve_query_set(
+            &gpu.query_set,
+            0..frame.scopes.len() as u32 * 2,
+            &gpu.readbacks[frame.readback].buffer,
+            0,
+        );
+    }
+
+    /// Finishes the frame after it was submitted, and collects the GPU timings of earlier ones.
+    pub fn end_frame(&mut self, device: &wgpu::Device, frame_index: u64) {
+        if let Some(gpu) = &mut self.gpu {
+            if let Some(frame) = gpu.frame.take().filter(|frame| !frame.scopes.is_empty()) {
+                let size = frame.scopes.len() as wgpu::BufferAddress * 16;
+                let readback = &mut gpu.readbacks[frame.readback];
+                let mapping = readback.buffer.slice(..size).map_async(wgpu::MapMode::Read);
+                readback.pending = Some(Pending {
+                    frame_index,
+                    scopes: frame.scopes,
+                    origin: frame.origin,
+                    size,
+                    mapping: Mutex::new(Box::pin(mapping)),
+                });
+            }
+
+            for (index, scopes) in gpu.poll(device) {
+                if let Some(frame) = self.history.iter_mut().find(|f| f.index == index) {
+                    frame.gpu = scopes;
+                }
+            }
+        }
+
+        if !self.enabled {
+            return;
+        }
+        let now = self.millis(Instant::now());
+        let next = FrameProfile {
+            start: now,
+            ..Default::default()
+        };
+        let mut frame = std::mem::replace(&mut self.frame, next);
+        frame.index = frame_index;
+        frame.duration = now - frame.start;
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(frame);
+    }
+
+    /// The recent frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &FrameProfile> {
+        self.history.iter()
+    }
+
+    /// Averages over the recent frames, GPU scopes over the frames that have them.
+    pub fn summary(&self) -> Summary {
+        let frame = match self.history.len() {
+            0 => 0.,
+            len => self.history.iter().map(|f| f.duration).sum::<f64>() / len as f64,
+        };
+        Summary {
+            frame,
+            cpu: Self::averages(self.history.iter().map(|f| &f.cpu)),
+            gpu: Self::averages(self.history.iter().map(|f| &f.gpu)),
+        }
+    }
+
+    /// Averages scopes by name and depth over the frames recording any, in the order they first
+    /// appeared in.
+    fn averages<'a>(frames: impl Iterator<Item = &'a Vec<Scope>>) -> Vec<ScopeAverage> {
+        let mut averages: Vec<ScopeAverage> = Vec::new();
+        let mut frame_count = 0;
+        for scopes in frames.filter(|scopes| !scopes.is_empty()) {
+            frame_count += 1;
+            for scope in scopes {
+                match averages
+                    .iter_mut()
+                    .find(|a| a.name == scope.name && a.depth == scope.depth)
+                {
+                    Some(average) => average.duration += scope.duration,
+                    None => averages.push(ScopeAverage {
+                        name: scope.name.clone(),
+                        depth: scope.depth,
+                        duration: scope.duration,
+                    }),
+                }
+            }
+        }
+        for average in &mut averages {
+            average.duration /= frame_count as f64;
+        }
+        averages
+    }
+
+    /// Writes the recent frames in the Chrome trace event format, for `chrome://tracing` or
+    /// Perfetto. CPU scopes are on the first thread, GPU scopes on the second.
+    pub fn save_trace(&self, path: &Path) -> Result<()> {
+        let mut events = vec![
+            r#"{"name":"thread_name","ph":"M","pid":0,"tid":0,"args":{"name":"CPU"}}"#.to_owned(),
+            r#"{"name":"thread_name","ph":"M","pid":0,"tid":1,"args":{"name":"GPU"}}"#.to_owned(),
+        ];
+        for frame in &self.history {
+            let frame_scope = Scope {
+                name: format!("Frame {}", frame.index),
+                start: frame.start,
+                duration: frame.duration,
+                depth: 0,
+            };
+            let cpu = std::iter::once(&frame_scope).chain(&frame.cpu);
+            for (tid, scope) in cpu.map(|s| (0, s)).chain(frame.gpu.iter().map(|s| (1, s))) {
+                events.push(format!(
+                    r#"{{"name":"{}","ph":"X","pid":0,"tid":{},"ts":{:.3},"dur":{:.3}}}"#,
+                    escape(&scope.name),
+                    tid,
+                    scope.start * 1000.,
+                    scope.duration * 1000.
+                ));
+            }
+        }
+
+        let mut trace = String::from("{\"traceEvents\":[\n");
+        for (index, event) in events.iter().enumerate() {
+            let separator = if index + 1 < events.len() { "," } else { "" };
+            writeln!(trace, "{}{}", event, separator).unwrap();
+        }
+        trace.push_str("]}\n");
+        fs::write(path, trace).with_context(|| format!("Failed to write trace {:?}", path))
+    }
+}
+
+fn escape(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,104 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    num::NonZeroU32,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use pollster::FutureExt;
+
+/// Copy of a frame, readable once the commands recording it were submitted.
+pub struct Screenshot {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool,
+}
+
+impl Screenshot {
+    /// Records copying `texture`, which needs `COPY_SRC` usage.
+    pub fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let bgra = match format {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            _ => bail!("Can't take screenshots of {:?} surfaces", format),
+        };
+
+        // Rows of buffer copies have to be aligned, the padding is skipped when saving.
+        let bytes_per_row = width * 4;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row =
+            bytes_per_row + (alignment - bytes_per_row % alignment) % alignment;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot buffer"),
+            size: (padded_bytes_per_row * height) as _,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Ok(Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            bgra,
+        })
+    }
+
+    /// Waits for the copy and writes it as a binary PPM.
+    pub fn save(self, device: &wgpu::Device, path: &Path) -> Result<()> {
+        let slice = self.buffer.slice(..);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        mapping
+            .block_on()
+            .context("Failed to read back the screenshot")?;
+
+        let data = slice.get_mapped_range();
+        let file = File::create(path).with_context(|| format!("Failed to create {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for row in data
+            .chunks(self.padded_bytes_per_row as _)
+            .take(self.height as _)
+        {
+            for pixel in row[..self.width as usize * 4].chunks(4) {
+                let rgb = if self.bgra {
+                    [pixel[2], pixel[1], pixel[0]]
+                } else {
+                    [pixel[0], pixel[1], pixel[2]]
+                };
+                writer.write_all(&rgb)?;
+            }
+        }
+        writer
+            .flush()
+            .with_context(|| format!("Failed to write {:?}", path))
+    }
+}
@@ -0,0 +1,378 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{vec2, Mat4, Vec2};
+
+use crate::{
+    entity,
+    renderer::{self, graph, uniform::UniformBuffer},
+};
+
+pub const VELOCITY_PASS: &str = "taa_velocity";
+pub const RESOLVE_PASS: &str = "taa_resolve";
+pub const HISTORY_PASS: &str = "taa_history";
+
+const VELOCITY: graph::ResourceId = "velocity";
+
+/// Motion vectors written by the main pass, see `renderer::TargetConfig::motion_vector_format`.
+pub const MOTION: graph::ResourceId = "motion";
+/// Motion in UV units in red and green, alpha is 1 where an object wrote its motion.
+pub const MOTION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const HISTORY: graph::ResourceId = "taa_history";
+
+/// Anti-aliased color, in the same format as the input.
+pub const OUTPUT: graph::ResourceId = "taa_output";
+
+const JITTER_SEQUENCE_LENGTH: u64 = 8;
+
+fn halton(mut index: u64, base: u64) -> f32 {
+    let mut fraction = 1.;
+    let mut result = 0.;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Sub-pixel projection offset in NDC for the given frame, cycling through a Halton(2, 3)
+/// sequence.
+pub fn jitter(frame_index: u64, width: u32, height: u32) -> Vec2 {
+    let index = frame_index % JITTER_SEQUENCE_LENGTH + 1;
+    let offset = vec2(halton(index, 2), halton(index, 3)) - Vec2::splat(0.5);
+    offset * 2. / vec2(width as f32, height as f32)
+}
+
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+#[repr(C)]
+struct Uniforms {
+    inverse_view_projection: Mat4,
+    previous_view_projection: Mat4,
+    jitter: Vec2,
+    history_valid: u32,
+    _pad0: [u8; 4],
+}
+
+struct BindGroups {
+    velocity: wgpu::BindGroup,
+    resolve: wgpu::BindGroup,
+    history: wgpu::BindGroup,
+}
+
+struct RenderPipelines {
+    velocity: wgpu::RenderPipeline,
+    resolve: wgpu::RenderPipeline,
+    history: wgpu::RenderPipeline,
+}
+
+struct BindGroupLayouts {
+    velocity: wgpu::BindGroupLayout,
+    resolve: wgpu::BindGroupLayout,
+    history: wgpu::BindGroupLayout,
+}
+
+pub struct PipelineState {
+    input: graph::ResourceId,
+    uniform_buffer: UniformBuffer<Uniforms>,
+    sampler: wgpu::Sampler,
+    bind_group_layouts: BindGroupLayouts,
+    bind_groups: Option<BindGroups>,
+    bind_group_generation: u64,
+    render_pipelines: RenderPipelines,
+    /// Unjittered view projection of the last frame.
+    previous_view_projection: Mat4,
+    /// Graph generation the history was written with, if it is usable.
+    history_generation: Option<u64>,
+}
+
+impl PipelineState {
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+    const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+
+    pub fn new(device: &wgpu::Device, input: graph::ResourceId) -> Self {
+        let uniform_buffer = UniformBuffer::new(device, "Uniform buffer", &Uniforms::default());
+        let sampler = Self::make_sampler(device);
+        let bind_group_layouts = Self::make_bind_group_layouts(device);
+        let render_pipelines = Self::make_render_pipelines(device, &bind_group_layouts);
+
+        Self {
+            input,
+            uniform_buffer,
+            sampler,
+            bind_group_layouts,
+            bind_groups: None,
+            bind_group_generation: 0,
+            render_pipelines,
+            previous_view_projection: Mat4::IDENTITY,
+            history_generation: None,
+        }
+    }
+
+    fn make_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("TAA history sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        })
+    }
+
+    fn make_bind_group_layouts(device: &wgpu::Device) -> BindGroupLayouts {
+        let uniform_entry = wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: UniformBuffer::<Uniforms>::binding_size(),
+            },
+            count: None,
+        };
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let float = |filterable| wgpu::TextureSampleType::Float { filterable };
+
+        BindGroupLayouts {
+            velocity: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    uniform_entry,
+                    texture_entry(1, wgpu::TextureSampleType::Depth),
+                    texture_entry(2, float(false)),
+                ],
+            }),
+            resolve: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[
+                    uniform_entry,
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                    texture_entry(2, float(false)),
+                    texture_entry(3, float(false)),
+                    texture_entry(4, float(true)),
+                ],
+            }),
+            history: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[texture_entry(0, float(false))],
+            }),
+        }
+    }
+
+    fn make_bind_groups(
+        &self,
+        device: &wgpu::Device,
+        graph: &graph::RenderGraph,
+    ) -> Option<BindGroups> {
+        let texture = |binding, id| {
+            Some(wgpu::BindGroupEntry {
+                binding,
+                resource: wgpu::BindingResource::TextureView(graph.view(id)?),
+            })
+        };
+        let uniform = wgpu::BindGroupEntry {
+            binding: 0,
+            resource: self.uniform_buffer.as_entire_binding(),
+        };
+
+        Some(BindGroups {
+            velocity: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layouts.velocity,
+                entries: &[
+                    uniform.clone(),
+                    texture(1, graph::DEPTH)?,
+                    texture(2, MOTION)?,
+                ],
+            }),
+            resolve: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layouts.resolve,
+                entries: &[
+                    uniform,
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    texture(2, self.input)?,
+                    texture(3, VELOCITY)?,
+                    texture(4, HISTORY)?,
+                ],
+            }),
+            history: device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layouts.history,
+                entries: &[texture(0, OUTPUT)?],
+            }),
+        })
+    }
+
+    fn make_render_pipelines(
+        device: &wgpu::Device,
+        bind_group_layouts: &BindGroupLayouts,
+    ) -> RenderPipelines {
+        let make_render_pipeline =
+            |label, shader, bind_group_layout, format: wgpu::TextureFormat| {
+                let shader_module = device.create_shader_module(&shader);
+
+                let pipeline_layout =
+                    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                        label: None,
+                        bind_group_layouts: &[bind_group_layout],
+                        push_constant_ranges: &[],
+                    });
+
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader_module,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader_module,
+                        entry_point: "fs_main",
+                        targets: &[format.into()],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+            };
+
+        RenderPipelines {
+            velocity: make_render_pipeline(
+                "TAA velocity",
+                wgpu::include_wgsl!("velocity.wgsl"),
+                &bind_group_layouts.velocity,
+                Self::VELOCITY_FORMAT,
+            ),
+            resolve: make_render_pipeline(
+                "TAA resolve",
+                wgpu::include_wgsl!("resolve.wgsl"),
+                &bind_group_layouts.resolve,
+                Self::FORMAT,
+            ),
+            history: make_render_pipeline(
+                "TAA history",
+                wgpu::include_wgsl!("copy.wgsl"),
+                &bind_group_layouts.history,
+                Self::FORMAT,
+            ),
+        }
+    }
+
+    /// Makes the next frame ignore the history, e.g. after TAA was turned back on.
+    pub fn reset_history(&mut self) {
+        self.history_generation = None;
+    }
+
+    /// Uploads this frame's camera and rebinds the textures after the graph reallocated them.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        graph: &graph::RenderGraph,
+        camera: &entity::Camera,
+    ) {
+        if self.bind_groups.is_none() || self.bind_group_generation != graph.generation() {
+            self.bind_groups = self.make_bind_groups(device, graph);
+            self.bind_group_generation = graph.generation();
+        }
+
+        let view_projection = camera.view_projection().view_projection;
+        let unjittered_view_projection = entity::Camera {
+            jitter: Vec2::ZERO,
+            ..*camera
+        }
+        .view_projection()
+        .view_projection;
+
+        // Reallocated textures have lost their contents.
+        let history_valid = self.history_generation == Some(graph.generation());
+
+        let uniforms = Uniforms {
+            inverse_view_projection: view_projection.inverse(),
+            previous_view_projection: if history_valid {
+                self.previous_view_projection
+            } else {
+                unjittered_view_projection
+            },
+            jitter: camera.jitter,
+            history_valid: history_valid as _,
+            ..Default::default()
+        };
+        self.uniform_buffer.write(queue, &uniforms);
+
+        self.previous_view_projection = unjittered_view_projection;
+        self.history_generation = Some(graph.generation());
+    }
+}
+
+impl renderer::Pipeline for PipelineState {
+    fn setup(&self, graph: &mut graph::RenderGraph) {
+        graph.add_texture(MOTION, graph::TextureDesc::new(MOTION_FORMAT));
+        graph.add_texture(VELOCITY, graph::TextureDesc::new(Self::VELOCITY_FORMAT));
+        graph.add_texture(OUTPUT, graph::TextureDesc::new(Self::FORMAT));
+        graph.add_texture(
+            HISTORY,
+            graph::TextureDesc {
+                history: true,
+                ..graph::TextureDesc::new(Self::FORMAT)
+            },
+        );
+
+        let pass = |name, target, reads| graph::PassDesc {
+            name,
+            color_attachments: vec![graph::ColorAttachment {
+                target,
+                resolve_target: None,
+                clear: Some(wgpu::Color::BLACK),
+            }],
+            depth_attachment: None,
+            reads,
+        };
+
+        graph.add_pass(pass(VELOCITY_PASS, VELOCITY, vec![graph::DEPTH, MOTION]));
+        graph.add_pass(pass(
+            RESOLVE_PASS,
+            OUTPUT,
+            vec![self.input, VELOCITY, HISTORY],
+        ));
+        graph.add_pass(pass(HISTORY_PASS, HISTORY, vec![OUTPUT]));
+        for pass in [VELOCITY_PASS, RESOLVE_PASS, HISTORY_PASS] {
+            graph.add_draw(pass, self.name());
+        }
+    }
+
+    fn render<'a>(&'a self, pass: &str, render_pass: &mut wgpu::RenderPass<'a>) {
+        let bind_groups = match &self.bind_groups {
+            Some(bind_groups) => bind_groups,
+            None => return,
+        };
+
+        let (render_pipeline, bind_group) = match pass {
+            VELOCITY_PASS => (&self.render_pipelines.velocity, &bind_groups.velocity),
+            RESOLVE_PASS => (&self.render_pipelines.resolve, &bind_groups.resolve),
+            HISTORY_PASS => (&self.render_pipelines.history, &bind_groups.history),
+            _ => return,
+        };
+
+        render_pass.set_pipeline(render_pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
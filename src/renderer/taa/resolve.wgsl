@@ -0,0 +1,59 @@
+struct Uniforms {
+    inverse_view_projection: mat4x4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec2<f32>;
+    history_valid: u32;
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+@group(0) @binding(1)
+var linear_sampler: sampler;
+@group(0) @binding(2)
+var color: texture_2d<f32>;
+@group(0) @binding(3)
+var velocity: texture_2d<f32>;
+@group(0) @binding(4)
+var history: texture_2d<f32>;
+
+@stage(vertex)
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - vec2<f32>(1.0), 0.0, 1.0);
+}
+
+@stage(fragment)
+fn fs_main(
+    @builtin(position) position: vec4<f32>,
+) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let dimensions = textureDimensions(color);
+    let current = textureLoad(color, pixel, 0).rgb;
+
+    if (uniforms.history_valid == 0u) {
+        return vec4<f32>(current, 1.0);
+    }
+
+    // Clamping the history to the current neighborhood rejects stale samples.
+    var minimum = current;
+    var maximum = current;
+    for (var y = -1; y <= 1; y = y + 1) {
+        for (var x = -1; x <= 1; x = x + 1) {
+            let neighbor_pixel = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), dimensions - vec2<i32>(1));
+            let neighbor = textureLoad(color, neighbor_pixel, 0).rgb;
+            minimum = min(minimum, neighbor);
+            maximum = max(maximum, neighbor);
+        }
+    }
+
+    let uv = position.xy / vec2<f32>(dimensions);
+    let history_uv = uv - textureLoad(velocity, pixel, 0).xy;
+    if (any(history_uv < vec2<f32>(0.0)) || any(history_uv > vec2<f32>(1.0))) {
+        return vec4<f32>(current, 1.0);
+    }
+
+    let previous = textureSampleLevel(history, linear_sampler, history_uv, 0.0).rgb;
+    return vec4<f32>(mix(clamp(previous, minimum, maximum), current, 0.1), 1.0);
+}
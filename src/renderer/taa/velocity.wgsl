@@ -0,0 +1,44 @@
+struct Uniforms {
+    inverse_view_projection: mat4x4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    jitter: vec2<f32>;
+    history_valid: u32;
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+@group(0) @binding(1)
+var depth: texture_depth_2d;
+@group(0) @binding(2)
+var motion: texture_2d<f32>;
+
+@stage(vertex)
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - vec2<f32>(1.0), 0.0, 1.0);
+}
+
+// Takes the motion vectors objects wrote in the main pass, and reprojects the other pixels with
+// last frame's camera, so that only camera motion is accounted for there.
+@stage(fragment)
+fn fs_main(
+    @builtin(position) position: vec4<f32>,
+) -> @location(0) vec2<f32> {
+    let object_motion = textureLoad(motion, vec2<i32>(position.xy), 0);
+    if (object_motion.a > 0.0) {
+        return object_motion.xy;
+    }
+
+    let uv = position.xy / vec2<f32>(textureDimensions(depth));
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let z = textureLoad(depth, vec2<i32>(position.xy), 0);
+
+    // Staying homogeneous keeps points on an infinite far plane finite.
+    let world = uniforms.inverse_view_projection * vec4<f32>(ndc, z, 1.0);
+    let previous = uniforms.previous_view_projection * world;
+
+    let velocity = (ndc - uniforms.jitter) - previous.xy / previous.w;
+    return velocity * vec2<f32>(0.5, -0.5);
+}
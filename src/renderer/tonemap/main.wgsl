@@ -0,0 +1,104 @@
+struct Uniforms {
+    exposure: f32;
+    tone_mapping: u32;
+    bloom_intensity: f32;
+    bloom_normalization: f32;
+    // Whether the output encodes linear colors on write.
+    srgb_output: u32;
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+@group(0) @binding(1)
+var hdr_color: texture_2d<f32>;
+@group(0) @binding(2)
+var linear_sampler: sampler;
+@group(0) @binding(3)
+var bloom: texture_2d<f32>;
+
+@stage(vertex)
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    return vec4<f32>(uv * 2.0 - vec2<f32>(1.0), 0.0, 1.0);
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (vec3<f32>(1.0) + color);
+}
+
+// Narkowicz's fit of the ACES filmic curve.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let x = color;
+    return clamp(
+        (x * (2.51 * x + vec3<f32>(0.03))) / (x * (2.43 * x + vec3<f32>(0.59)) + vec3<f32>(0.14)),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0),
+    );
+}
+
+// Wrensch's polynomial approximation of the AgX base contrast curve.
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - vec3<f32>(0.00232);
+}
+
+// Returns display encoded values, unlike the other operators.
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var x = inset * color;
+    x = clamp(log2(max(x, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    x = (x - vec3<f32>(min_ev)) / vec3<f32>(max_ev - min_ev);
+    return outset * agx_contrast(x);
+}
+
+@stage(fragment)
+fn fs_main(
+    @builtin(position) position: vec4<f32>,
+) -> @location(0) vec4<f32> {
+    var color = textureLoad(hdr_color, vec2<i32>(position.xy), 0).rgb;
+
+    let uv = position.xy / vec2<f32>(textureDimensions(hdr_color));
+    let bloom_color = textureSample(bloom, linear_sampler, uv).rgb * uniforms.bloom_normalization;
+    color = mix(color, bloom_color, uniforms.bloom_intensity) * uniforms.exposure;
+
+    // sRGB outputs encode linear colors on write, other outputs get display encoded ones.
+    var mapped: vec3<f32>;
+    if (uniforms.tone_mapping == 2u) {
+        mapped = max(agx(color), vec3<f32>(0.0));
+        if (uniforms.srgb_output != 0u) {
+            mapped = pow(mapped, vec3<f32>(2.2));
+        }
+    } else {
+        if (uniforms.tone_mapping == 0u) {
+            mapped = reinhard(color);
+        } else {
+            mapped = aces(color);
+        }
+        if (uniforms.srgb_output == 0u) {
+            mapped = pow(mapped, vec3<f32>(1.0 / 2.2));
+        }
+    }
+
+    return vec4<f32>(mapped, 1.0);
+}
@@ -0,0 +1,304 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::renderer::{self, bloom, graph, uniform::UniformBuffer};
+
+pub const PASS: &str = "tonemap";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ToneMapping {
+    Reinhard,
+    Aces,
+    AgX,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 3] = [ToneMapping::Reinhard, ToneMapping::Aces, ToneMapping::AgX];
+}
+
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+#[repr(C)]
+struct Uniforms {
+    exposure: f32,
+    tone_mapping: u32,
+    bloom_intensity: f32,
+    bloom_normalization: f32,
+    srgb_output: u32,
+    _pad0: [u8; 12],
+}
+
+impl Uniforms {
+    fn new(
+        tone_mapping: ToneMapping,
+        exposure: f32,
+        bloom_intensity: f32,
+        srgb_output: bool,
+    ) -> Self {
+        Self {
+            exposure: exposure.exp2(),
+            tone_mapping: tone_mapping as _,
+            bloom_intensity,
+            // The bloom output is the sum of all its mips.
+            bloom_normalization: 1. / bloom::MIP_COUNT as f32,
+            srgb_output: srgb_output as _,
+            ..Default::default()
+        }
+    }
+}
+
+pub struct PipelineState {
+    input: graph::ResourceId,
+    bloom: graph::ResourceId,
+    output: graph::ResourceId,
+    uniform_buffer: UniformBuffer<Uniforms>,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: Option<wgpu::BindGroup>,
+    bind_group_generation: u64,
+    render_pipeline: wgpu::RenderPipeline,
+    tone_mapping: ToneMapping,
+    exposure: f32,
+    bloom_intensity: f32,
+    /// Whether the output format encodes linear colors on write, otherwise the shader does.
+    srgb_output: bool,
+}
+
+impl PipelineState {
+    pub fn new(
+        device: &wgpu::Device,
+        input: graph::ResourceId,
+        bloom: graph::ResourceId,
+        output_format: wgpu::TextureFormat,
+    ) -> Self {
+        let tone_mapping = ToneMapping::AgX;
+        let exposure = 0.;
+        let bloom_intensity = 0.;
+        let srgb_output = output_format.describe().srgb;
+
+        let uniform_buffer = UniformBuffer::new(
+            device,
+            "Uniform buffer",
+            &Uniforms::new(tone_mapping, exposure, bloom_intensity, srgb_output),
+        );
+        let sampler = Self::make_sampler(device);
+        let bind_group_layout = Self::make_bind_group_layout(device);
+        let render_pipeline = Self::make_render_pipeline(device, &bind_group_layout, output_format);
+
+        Self {
+            input,
+            bloom,
+            output: graph::SURFACE,
+            uniform_buffer,
+            sampler,
+            bind_group_layout,
+            bind_group: None,
+            bind_group_generation: 0,
+            render_pipeline,
+            tone_mapping,
+            exposure,
+            bloom_intensity,
+            srgb_output,
+        }
+    }
+
+    fn make_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Tone mapping sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        })
+    }
+
+    fn make_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: UniformBuffer::<Uniforms>::binding_size(),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        })
+    }
+
+    fn make_bind_group(
+        &self,
+        device: &wgpu::Device,
+        input_view: &wgpu::TextureView,
+        bloom_view: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(bloom_view),
+                },
+            ],
+        })
+    }
+
+    fn make_render_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        output_format: wgpu::TextureFormat,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(&wgpu::include_wgsl!("main.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Tone mapping"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fs_main",
+                targets: &[output_format.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    /// Exposure in EV stops.
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    pub fn set_tone_mapping(&mut self, queue: &wgpu::Queue, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+        self.write_uniforms(queue);
+    }
+
+    pub fn set_exposure(&mut self, queue: &wgpu::Queue, exposure: f32) {
+        self.exposure = exposure;
+        self.write_uniforms(queue);
+    }
+
+    pub fn set_bloom_intensity(&mut self, queue: &wgpu::Queue, bloom_intensity: f32) {
+        self.bloom_intensity = bloom_intensity;
+        self.write_uniforms(queue);
+    }
+
+    fn write_uniforms(&self, queue: &wgpu::Queue) {
+        let uniforms = Uniforms::new(
+            self.tone_mapping,
+            self.exposure,
+            self.bloom_intensity,
+            self.srgb_output,
+        );
+        self.uniform_buffer.write(queue, &uniforms);
+    }
+
+    pub fn set_input(&mut self, input: graph::ResourceId) {
+        self.input = input;
+        self.bind_group = None;
+    }
+
+    /// Target of the tone mapped image, which has to be in the output format the pipeline was
+    /// created with.
+    pub fn set_output(&mut self, output: graph::ResourceId) {
+        self.output = output;
+    }
+
+    /// Rebinds the input textures after the graph reallocated it.
+    pub fn update(&mut self, device: &wgpu::Device, graph: &graph::RenderGraph) {
+        if self.bind_group.is_some() && self.bind_group_generation == graph.generation() {
+            return;
+        }
+        self.bind_group = match (graph.view(self.input), graph.view(self.bloom)) {
+            (Some(input_view), Some(bloom_view)) => {
+                Some(self.make_bind_group(device, input_view, bloom_view))
+            }
+            _ => None,
+        };
+        self.bind_group_generation = graph.generation();
+    }
+}
+
+impl renderer::Pipeline for PipelineState {
+    fn setup(&self, graph: &mut graph::RenderGraph) {
+        graph.add_pass(graph::PassDesc {
+            name: PASS,
+            color_attachments: vec![graph::ColorAttachment {
+                target: self.output,
+                resolve_target: None,
+                clear: Some(wgpu::Color::BLACK),
+            }],
+            depth_attachment: None,
+            reads: vec![self.input, self.bloom],
+        });
+        graph.add_draw(PASS, self.name());
+    }
+
+    fn render<'a>(&'a self, pass: &str, render_pass: &mut wgpu::RenderPass<'a>) {
+        if pass != PASS {
+            return;
+        }
+        if let Some(bind_group) = &self.bind_group {
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+}
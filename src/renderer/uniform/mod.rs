@@ -0,0 +1,41 @@
+use std::{marker::PhantomData, mem::size_of};
+
+use bytemuck::{bytes_of, Pod};
+use wgpu::util::DeviceExt;
+
+/// Uniform buffer holding a single `T`, updated through the queue.
+///
+/// `Queue::write_buffer` copies the data into a staging buffer right away and uploads it before
+/// the next submission, so writing never waits on frames still in flight on the GPU.
+pub struct UniformBuffer<T> {
+    buffer: wgpu::Buffer,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> UniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, label: &str, value: &T) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytes_of(value),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            buffer,
+            _marker: PhantomData,
+        }
+    }
+
+    /// `min_binding_size` for bind group layout entries of this buffer.
+    pub fn binding_size() -> Option<wgpu::BufferSize> {
+        wgpu::BufferSize::new(size_of::<T>() as _)
+    }
+
+    pub fn as_entire_binding(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, bytes_of(value));
+    }
+}
@@ -0,0 +1,142 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{vec2, Mat4, Vec2, Vec3};
+
+use crate::{entity, renderer::uniform::UniformBuffer};
+
+/// Group index of the view in every pipeline layout. Shaders get the matching `view` binding from
+/// `view.wgsl`, which `PipelineBuilder::build` prepends to their source at runtime.
+pub const BIND_GROUP: u32 = 0;
+
+#[derive(Debug, Copy, Clone, Default, Pod, Zeroable)]
+#[repr(C)]
+struct Uniforms {
+    view: Mat4,
+    projection: Mat4,
+    view_projection: Mat4,
+    inverse_view: Mat4,
+    inverse_projection: Mat4,
+    inverse_view_projection: Mat4,
+    previous_view: Mat4,
+    previous_projection: Mat4,
+    previous_view_projection: Mat4,
+    camera_position: Vec3,
+    time: f32,
+    viewport_size: Vec2,
+    jitter: Vec2,
+}
+
+impl Uniforms {
+    fn new(
+        camera: &entity::Camera,
+        previous: &entity::ViewProjection,
+        time: f32,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let entity::ViewProjection {
+            view,
+            projection,
+            view_projection,
+        } = camera.view_projection();
+
+        Self {
+            view,
+            projection,
+            view_projection,
+            inverse_view: view.inverse(),
+            inverse_projection: projection.inverse(),
+            inverse_view_projection: view_projection.inverse(),
+            previous_view: previous.view,
+            previous_projection: previous.projection,
+            previous_view_projection: previous.view_projection,
+            camera_position: camera.transform.position,
+            time,
+            viewport_size: vec2(width as f32, height as f32),
+            jitter: camera.jitter,
+        }
+    }
+}
+
+/// Per-frame camera data shared by every pipeline at group `BIND_GROUP`.
+pub struct ViewBinding {
+    uniform_buffer: UniformBuffer<Uniforms>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    /// Unjittered view and projection of the last update, for motion vectors.
+    previous: Option<entity::ViewProjection>,
+}
+
+impl ViewBinding {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let uniform_buffer =
+            UniformBuffer::new(device, "View uniform buffer", &Uniforms::default());
+        let bind_group_layout = Self::make_bind_group_layout(device);
+        let bind_group = Self::make_bind_group(device, &bind_group_layout, &uniform_buffer);
+
+        Self {
+            uniform_buffer,
+            bind_group_layout,
+            bind_group,
+            previous: None,
+        }
+    }
+
+    fn make_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("View"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: UniformBuffer::<Uniforms>::binding_size(),
+                },
+                count: None,
+            }],
+        })
+    }
+
+    fn make_bind_group(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        uniform_buffer: &UniformBuffer<Uniforms>,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("View"),
+            layout: bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+        })
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        camera: &entity::Camera,
+        time: f32,
+        width: u32,
+        height: u32,
+    ) {
+        let unjittered = entity::Camera {
+            jitter: Vec2::ZERO,
+            ..*camera
+        }
+        .view_projection();
+        let previous = self.previous.unwrap_or(unjittered);
+
+        let uniforms = Uniforms::new(camera, &previous, time, width, height);
+        self.uniform_buffer.write(queue, &uniforms);
+        self.previous = Some(unjittered);
+    }
+}
@@ -0,0 +1,36 @@
+struct View {
+    view: mat4x4<f32>;
+    projection: mat4x4<f32>;
+    view_projection: mat4x4<f32>;
+    inverse_view: mat4x4<f32>;
+    inverse_projection: mat4x4<f32>;
+    inverse_view_projection: mat4x4<f32>;
+    // Last frame's matrices without jitter.
+    previous_view: mat4x4<f32>;
+    previous_projection: mat4x4<f32>;
+    previous_view_projection: mat4x4<f32>;
+    camera_position: vec3<f32>;
+    // Simulated seconds passed to `Renderer::render`, stops while paused.
+    time: f32;
+    viewport_size: vec2<f32>;
+    // Sub-pixel offset in NDC applied by `projection`.
+    jitter: vec2<f32>;
+};
+
+@group(0) @binding(0)
+var<uniform> view: View;
+
+// Output of the `fs_motion` entry points, used when the main pass has a motion vector target.
+struct MotionOut {
+    @location(0) color: vec4<f32>;
+    @location(1) motion: vec4<f32>;
+};
+
+// How far the fragment moved in UV units since last frame, from its clip positions in both
+// frames. The alpha marks the pixel as covered by an object with motion vectors.
+fn motion_vector(clip_position: vec4<f32>, previous_clip_position: vec4<f32>) -> vec4<f32> {
+    let current = clip_position.xy / clip_position.w - view.jitter;
+    let previous = previous_clip_position.xy / previous_clip_position.w;
+    return vec4<f32>((current - previous) * vec2<f32>(0.5, -0.5), 0.0, 1.0);
+}
+
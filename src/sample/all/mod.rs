@@ -0,0 +1,98 @@
+use std::f32::consts::PI;
+
+use anyhow::Result;
+
+use crate::{
+    entity,
+    renderer::{billboard, cube, particles, Renderer},
+    sample::{spin, Sample},
+};
+
+struct Pipelines {
+    cube: cube::PipelineState,
+    billboard: billboard::PipelineState,
+    particles: particles::PipelineState,
+}
+
+pub struct AllSample {
+    scene: entity::Scene,
+    pipelines: Option<Pipelines>,
+}
+
+impl AllSample {
+    pub fn boxed(scene: entity::Scene) -> Box<dyn Sample> {
+        Box::new(Self {
+            scene,
+            pipelines: None,
+        })
+    }
+}
+
+impl Sample for AllSample {
+    fn setup(&mut self, renderer: &Renderer) {
+        let device = renderer.device();
+        let target_config = renderer.target_config();
+        let pipeline_cache = renderer.pipeline_cache();
+        let view = renderer.view();
+        self.pipelines = Some(Pipelines {
+            cube: cube::PipelineState::new(
+                device,
+                pipeline_cache,
+                &target_config,
+                view,
+                &self.scene,
+            ),
+            billboard: billboard::PipelineState::new(
+                device,
+                pipeline_cache,
+                &target_config,
+                view,
+                &self.scene,
+            ),
+            particles: particles::PipelineState::new(
+                device,
+                pipeline_cache,
+                &target_config,
+                view,
+                &self.scene,
+            ),
+        });
+    }
+
+    fn update(&mut self, dt: f32) {
+        spin(&mut self.scene.cube.transform, PI * 0.6, dt);
+        spin(&mut self.scene.particle_system.transform, PI * 0.06, dt);
+    }
+
+    fn render(&mut self, renderer: &mut Renderer, scene: &entity::Scene, time: f32) -> Result<()> {
+        match &mut self.pipelines {
+            Some(pipelines) => {
+                pipelines.cube.update(renderer.queue(), scene);
+                pipelines.billboard.update(renderer.queue(), scene);
+                pipelines.particles.update(renderer.queue(), scene);
+                renderer.render(
+                    &scene.camera,
+                    time,
+                    &mut [
+                        &mut pipelines.cube,
+                        &mut pipelines.billboard,
+                        &mut pipelines.particles,
+                    ],
+                )
+            }
+            None => renderer.render(&scene.camera, time, &mut []),
+        }
+    }
+
+    fn teardown(&mut self) {
+        self.pipelines = None;
+    }
+
+    fn scene(&self) -> &entity::Scene {
+        &self.scene
+    }
+
+    fn scene_mut(&mut self) -> &mut entity::Scene {
+        &mut self.scene
+    }
+}
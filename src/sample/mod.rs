@@ -0,0 +1,87 @@
+//! Demos selectable at runtime, each animating and drawing its own scene.
+
+use anyhow::Result;
+use glam::{Quat, Vec3};
+
+use crate::{
+    entity,
+    input::Action,
+    renderer::{billboard, cube, particles, Renderer},
+};
+
+pub mod all;
+pub mod single;
+
+use single::SinglePipelineSample;
+
+/// A demo owning its scene and the pipelines drawing it. Only the selected sample has its
+/// pipelines set up, the others keep their scene until they are selected again.
+pub trait Sample {
+    /// Creates the pipelines. Called when the sample is selected, and again after the renderer
+    /// recreated its device. Pipelines follow changes of the render targets on their own.
+    fn setup(&mut self, renderer: &Renderer);
+
+    /// Advances the animation by one fixed step of `dt` seconds.
+    fn update(&mut self, _dt: f32) {}
+
+    /// Sees the actions the camera controller didn't use before the application does. Returns
+    /// whether the sample uses the action.
+    fn input(&mut self, _action: Action, _pressed: bool) -> bool {
+        false
+    }
+
+    /// Draws `scene`, this sample's scene blended between the last two steps.
+    fn render(&mut self, renderer: &mut Renderer, scene: &entity::Scene, time: f32) -> Result<()>;
+
+    /// Drops the pipelines when another sample is selected.
+    fn teardown(&mut self);
+
+    fn scene(&self) -> &entity::Scene;
+
+    fn scene_mut(&mut self) -> &mut entity::Scene;
+}
+
+pub struct SampleInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Creates the sample with its own copy of the loaded scene.
+    pub new: fn(entity::Scene) -> Box<dyn Sample>,
+}
+
+/// Every sample, in the order of their indices.
+pub const SAMPLES: &[SampleInfo] = &[
+    SampleInfo {
+        name: "all",
+        description: "The cube, billboard and particles together",
+        new: all::AllSample::boxed,
+    },
+    SampleInfo {
+        name: "particles",
+        description: "A slowly turning cloud of instanced particles",
+        new: SinglePipelineSample::<particles::PipelineState>::boxed,
+    },
+    SampleInfo {
+        name: "cube",
+        description: "A spinning cube",
+        new: SinglePipelineSample::<cube::PipelineState>::boxed,
+    },
+    SampleInfo {
+        name: "billboard",
+        description: "A camera facing quad following the cube",
+        new: SinglePipelineSample::<billboard::PipelineState>::boxed,
+    },
+];
+
+/// Index of the sample with this name or index.
+pub fn find(name_or_index: &str) -> Option<usize> {
+    name_or_index
+        .parse()
+        .ok()
+        .filter(|&index| index < SAMPLES.len())
+        .or_else(|| SAMPLES.iter().position(|s| s.name == name_or_index))
+}
+
+/// Turns `transform` around the Y axis at `speed` radians per second.
+fn spin(transform: &mut entity::Transform, speed: f32, dt: f32) {
+    transform.rotation *= Quat::from_axis_angle(Vec3::Y, speed * dt);
+}
@@ -0,0 +1,128 @@
+use std::f32::consts::PI;
+
+use anyhow::Result;
+
+use crate::{
+    entity,
+    renderer::{self, billboard, cube, particles, Renderer},
+    sample::{spin, Sample},
+};
+
+/// A pipeline drawing a whole sample on its own, see `SinglePipelineSample`.
+pub trait SamplePipeline: renderer::Pipeline + Sized + 'static {
+    fn create(renderer: &Renderer, scene: &entity::Scene) -> Self;
+
+    /// Uploads the scene blended for this frame.
+    fn update_scene(&mut self, queue: &wgpu::Queue, scene: &entity::Scene);
+
+    /// Advances the animation of what the pipeline draws by one fixed step of `dt` seconds.
+    fn animate(scene: &mut entity::Scene, dt: f32);
+}
+
+/// Draws its scene with nothing but a `P`.
+pub struct SinglePipelineSample<P> {
+    scene: entity::Scene,
+    pipeline: Option<P>,
+}
+
+impl<P: SamplePipeline> SinglePipelineSample<P> {
+    pub fn boxed(scene: entity::Scene) -> Box<dyn Sample> {
+        Box::new(Self {
+            scene,
+            pipeline: None,
+        })
+    }
+}
+
+impl<P: SamplePipeline> Sample for SinglePipelineSample<P> {
+    fn setup(&mut self, renderer: &Renderer) {
+        self.pipeline = Some(P::create(renderer, &self.scene));
+    }
+
+    fn update(&mut self, dt: f32) {
+        P::animate(&mut self.scene, dt);
+    }
+
+    fn render(&mut self, renderer: &mut Renderer, scene: &entity::Scene, time: f32) -> Result<()> {
+        match &mut self.pipeline {
+            Some(pipeline) => {
+                pipeline.update_scene(renderer.queue(), scene);
+                renderer.render(&scene.camera, time, &mut [pipeline])
+            }
+            None => renderer.render(&scene.camera, time, &mut []),
+        }
+    }
+
+    fn teardown(&mut self) {
+        self.pipeline = None;
+    }
+
+    fn scene(&self) -> &entity::Scene {
+        &self.scene
+    }
+
+    fn scene_mut(&mut self) -> &mut entity::Scene {
+        &mut self.scene
+    }
+}
+
+impl SamplePipeline for cube::PipelineState {
+    fn create(renderer: &Renderer, scene: &entity::Scene) -> Self {
+        Self::new(
+            renderer.device(),
+            renderer.pipeline_cache(),
+            &renderer.target_config(),
+            renderer.view(),
+            scene,
+        )
+    }
+
+    fn update_scene(&mut self, queue: &wgpu::Queue, scene: &entity::Scene) {
+        self.update(queue, scene);
+    }
+
+    fn animate(scene: &mut entity::Scene, dt: f32) {
+        spin(&mut scene.cube.transform, PI * 0.6, dt);
+    }
+}
+
+impl SamplePipeline for billboard::PipelineState {
+    fn create(renderer: &Renderer, scene: &entity::Scene) -> Self {
+        Self::new(
+            renderer.device(),
+            renderer.pipeline_cache(),
+            &renderer.target_config(),
+            renderer.view(),
+            scene,
+        )
+    }
+
+    fn update_scene(&mut self, queue: &wgpu::Queue, scene: &entity::Scene) {
+        self.update(queue, scene);
+    }
+
+    /// The billboard is placed by the cube's transform.
+    fn animate(scene: &mut entity::Scene, dt: f32) {
+        spin(&mut scene.cube.transform, PI * 0.6, dt);
+    }
+}
+
+impl SamplePipeline for particles::PipelineState {
+    fn create(renderer: &Renderer, scene: &entity::Scene) -> Self {
+        Self::new(
+            renderer.device(),
+            renderer.pipeline_cache(),
+            &renderer.target_config(),
+            renderer.view(),
+            scene,
+        )
+    }
+
+    fn update_scene(&mut self, queue: &wgpu::Queue, scene: &entity::Scene) {
+        self.update(queue, scene);
+    }
+
+    fn animate(scene: &mut entity::Scene, dt: f32) {
+        spin(&mut scene.particle_system.transform, PI * 0.06, dt);
+    }
+}
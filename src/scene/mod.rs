@@ -0,0 +1,216 @@
+//! The built in scene and a line based scene file format.
+
+use std::{f32::consts::PI, fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use glam::{vec3, EulerRot, Quat, Vec2, Vec3};
+
+use crate::entity;
+
+/// The scene shown without a scene file. The camera aspect ratio has to be set from the window.
+pub fn default() -> entity::Scene {
+    entity::Scene {
+        camera: entity::Camera {
+            transform: entity::Transform {
+                position: Vec3::ZERO,
+                rotation: Quat::IDENTITY,
+                ..Default::default()
+            },
+            projection: entity::Projection::Perspective(entity::Fov::Vertical(60.)),
+            aspect_ratio: 1.,
+            near: 0.1,
+            far: 1000.,
+            reverse_z: false,
+            jitter: Vec2::ZERO,
+        },
+        cube: entity::Cube {
+            transform: entity::Transform {
+                position: vec3(0., 0., 10.),
+                rotation: Quat::from_axis_angle(Vec3::X, PI * -0.125),
+                scale: Vec3::ONE,
+            },
+        },
+        particle_system: entity::ParticleSystem {
+            transform: entity::Transform {
+                position: vec3(0., 0., 10.),
+                rotation: Quat::from_axis_angle(Vec3::X, PI * -0.25),
+                scale: Vec3::ONE * 1.5,
+            },
+            max_count: 10000,
+            particle_size: 0.01,
+            seed: None,
+            lifetime: 0,
+            min_speed: 0.01,
+            max_speed: 1.,
+        },
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<entity::Scene> {
+    let path = path.as_ref();
+    let source =
+        fs::read_to_string(path).with_context(|| format!("Failed to read scene {:?}", path))?;
+    parse(&source).with_context(|| format!("Invalid scene {:?}", path))
+}
+
+/// Reads `key = values` lines on top of the default scene, `#` starts a comment. Vectors are
+/// whitespace separated, rotations are degrees around X, Y and Z, applied in Y, X, Z order:
+///
+/// ```text
+/// camera.position = 0 1 -2
+/// camera.fov = 75
+/// cube.rotation = -22.5 45 0
+/// particles.count = 50000
+/// particles.seed = 7
+/// ```
+pub fn parse(source: &str) -> Result<entity::Scene> {
+    let mut scene = default();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .with_context(|| format!("Line {}: expected `key = value`", number + 1))?;
+        let (key, value) = (key.trim(), value.trim());
+        set(&mut scene, key, value).with_context(|| format!("Line {}: {}", number + 1, key))?;
+    }
+
+    Ok(scene)
+}
+
+fn set(scene: &mut entity::Scene, key: &str, value: &str) -> Result<()> {
+    let (entity, property) = key.split_once('.').context("Expected `entity.property`")?;
+
+    let transform = match entity {
+        "camera" => &mut scene.camera.transform,
+        "cube" => &mut scene.cube.transform,
+        "particles" => &mut scene.particle_system.transform,
+        _ => bail!("Unknown entity {:?}", entity),
+    };
+    match property {
+        "position" => return parse_vec3(value).map(|v| transform.position = v),
+        "rotation" => return parse_rotation(value).map(|r| transform.rotation = r),
+        "scale" if entity != "camera" => return parse_vec3(value).map(|v| transform.scale = v),
+        _ => (),
+    }
+
+    let camera = &mut scene.camera;
+    let particle_system = &mut scene.particle_system;
+    match (entity, property) {
+        ("camera", "fov") => {
+            camera.projection = entity::Projection::Perspective(entity::Fov::Vertical(
+                value.parse().context("Expected degrees")?,
+            ))
+        }
+        ("camera", "near") => camera.near = value.parse().context("Expected a distance")?,
+        ("camera", "far") => camera.far = value.parse().context("Expected a distance")?,
+        ("particles", "count") => {
+            particle_system.max_count = value.parse().context("Expected a count")?
+        }
+        ("particles", "size") => {
+            particle_system.particle_size = value.parse().context("Expected a size")?
+        }
+        ("particles", "seed") => {
+            particle_system.seed = Some(value.parse().context("Expected an integer seed")?)
+        }
+        _ => bail!("Unknown property {:?}", property),
+    }
+    Ok(())
+}
+
+fn parse_vec3(value: &str) -> Result<Vec3> {
+    let components = value
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<f32>, _>>()
+        .context("Expected numbers")?;
+    match components[..] {
+        [x, y, z] => Ok(vec3(x, y, z)),
+        _ => bail!("Expected 3 components, got {}", components.len()),
+    }
+}
+
+fn parse_rotation(value: &str) -> Result<Quat> {
+    let degrees = parse_vec3(value)?;
+    Ok(Quat::from_euler(
+        EulerRot::YXZ,
+        degrees.y.to_radians(),
+        degrees.x.to_radians(),
+        degrees.z.to_radians(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        format!("{:#}", parse(source).unwrap_err())
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let scene = parse(
+            "
+            # The cube
+            cube.position = 1 2 3 # moved
+
+            particles.seed = 7
+            ",
+        )
+        .unwrap();
+        assert_eq!(scene.cube.transform.position, vec3(1., 2., 3.));
+        assert_eq!(scene.particle_system.seed, Some(7));
+    }
+
+    #[test]
+    fn set_overrides_one_property() {
+        let mut scene = default();
+        set(&mut scene, "camera.fov", "75").unwrap();
+        set(&mut scene, "particles.scale", "2 2 2").unwrap();
+        assert!(matches!(
+            scene.camera.projection,
+            entity::Projection::Perspective(entity::Fov::Vertical(fov)) if fov == 75.
+        ));
+        assert_eq!(scene.particle_system.transform.scale, Vec3::splat(2.));
+    }
+
+    #[test]
+    fn vectors_have_three_components() {
+        assert_eq!(parse_vec3(" 1  2 3 ").unwrap(), vec3(1., 2., 3.));
+        assert_eq!(
+            format!("{:#}", parse_vec3("1 2").unwrap_err()),
+            "Expected 3 components, got 2"
+        );
+        assert!(parse_vec3("1 2 x").is_err());
+        assert_eq!(
+            error("\ncube.position = 1 2 3 4"),
+            "Line 2: cube.position: Expected 3 components, got 4"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        assert_eq!(
+            error("light.position = 0 0 0"),
+            "Line 1: light.position: Unknown entity \"light\""
+        );
+        assert_eq!(
+            error("cube.fov = 60"),
+            "Line 1: cube.fov: Unknown property \"fov\""
+        );
+        assert_eq!(
+            error("camera.scale = 1 1 1"),
+            "Line 1: camera.scale: Unknown property \"scale\""
+        );
+        assert_eq!(
+            error("cube = 1"),
+            "Line 1: cube: Expected `entity.property`"
+        );
+        assert_eq!(error("cube.position"), "Line 1: expected `key = value`");
+    }
+}
@@ -0,0 +1,236 @@
+//! Frame timing, the fixed simulation step and frame rate limiting.
+
+use std::time::{Duration, Instant};
+
+/// Frame timing and the fixed step accumulator driving the simulation.
+///
+/// Call `tick` once per frame, then `next_step` in a loop to run the simulation steps that are
+/// due, and render with `alpha` to interpolate between the last two steps.
+#[derive(Debug, Copy, Clone)]
+pub struct Time {
+    pub fixed_step: Duration,
+    /// Longest frame time fed to the simulation, so that a stall doesn't have to be caught up
+    /// with a burst of steps.
+    pub max_delta: Duration,
+    last_frame: Instant,
+    delta: Duration,
+    elapsed: Duration,
+    accumulator: Duration,
+    paused: bool,
+    pending_steps: u32,
+}
+
+impl Time {
+    pub fn new(fixed_step: Duration) -> Self {
+        Self {
+            fixed_step,
+            max_delta: Duration::from_millis(250),
+            last_frame: Instant::now(),
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            accumulator: Duration::ZERO,
+            paused: false,
+            pending_steps: 0,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+        let delta = now - self.last_frame;
+        self.last_frame = now;
+        self.advance(delta);
+    }
+
+    /// `tick` with a frame that took `delta`.
+    fn advance(&mut self, delta: Duration) {
+        self.delta = delta;
+        if !self.paused {
+            self.accumulator += delta.min(self.max_delta);
+        }
+    }
+
+    /// Returns whether another fixed step is due this frame, consuming it.
+    pub fn next_step(&mut self) -> bool {
+        if self.paused {
+            if self.pending_steps == 0 {
+                return false;
+            }
+            self.pending_steps -= 1;
+        } else if self.accumulator >= self.fixed_step {
+            self.accumulator -= self.fixed_step;
+        } else {
+            return false;
+        }
+
+        self.elapsed += self.fixed_step;
+        true
+    }
+
+    /// Real time since the last frame, also while paused.
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// Simulated time, advancing by whole fixed steps.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Simulated time of the frame being rendered, between the previous and the latest step.
+    pub fn frame_time(&self) -> Duration {
+        self.elapsed
+            .saturating_sub(self.fixed_step)
+            .saturating_add(self.fixed_step.mul_f32(self.alpha()))
+            .min(self.elapsed)
+    }
+
+    /// How far between the previous and the latest step the frame is, from 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        if self.paused {
+            1.
+        } else {
+            self.accumulator.as_secs_f32() / self.fixed_step.as_secs_f32()
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.pending_steps = 0;
+    }
+
+    /// Runs a single step on the next frame while paused.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+}
+
+/// Caps the frame rate. The event loop sleeps until shortly before the next frame is due and
+/// `wait` spins through the rest, since sleeps overshoot by up to a scheduler tick.
+#[derive(Debug, Copy, Clone)]
+pub struct FrameLimiter {
+    interval: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    /// How long before the next frame the event loop wakes up to spin.
+    pub const SPIN_DURATION: Duration = Duration::from_millis(2);
+
+    /// `None` doesn't limit the frame rate.
+    pub fn new(max_frame_rate: Option<f32>) -> Self {
+        let mut limiter = Self {
+            interval: None,
+            next_frame: Instant::now(),
+        };
+        limiter.set_max_frame_rate(max_frame_rate);
+        limiter
+    }
+
+    pub fn max_frame_rate(&self) -> Option<f32> {
+        self.interval.map(|interval| 1. / interval.as_secs_f32())
+    }
+
+    pub fn set_max_frame_rate(&mut self, max_frame_rate: Option<f32>) {
+        self.interval = max_frame_rate.map(|rate| Duration::from_secs_f32(1. / rate));
+        self.next_frame = Instant::now();
+    }
+
+    /// When the event loop should wake up again, or `None` if the next frame is close enough to
+    /// `wait` for it.
+    pub fn sleep_until(&self) -> Option<Instant> {
+        self.interval?;
+        let wake_up = self.next_frame.checked_sub(Self::SPIN_DURATION)?;
+        (Instant::now() < wake_up).then_some(wake_up)
+    }
+
+    /// Blocks until the next frame is due and schedules the one after it.
+    pub fn wait(&mut self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        while Instant::now() < self.next_frame {
+            std::hint::spin_loop();
+        }
+
+        // Keeps a steady pace, but doesn't try to catch up after falling behind by a whole frame.
+        let now = Instant::now();
+        self.next_frame += interval;
+        if self.next_frame < now {
+            self.next_frame = now + interval;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(10);
+
+    /// Advances by `delta` and returns how many steps were due.
+    fn frame(time: &mut Time, delta: Duration) -> u32 {
+        time.advance(delta);
+        let mut steps = 0;
+        while time.next_step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn accumulator_keeps_the_remainder() {
+        let mut time = Time::new(STEP);
+        assert_eq!(frame(&mut time, Duration::from_millis(25)), 2);
+        assert_eq!(time.elapsed(), Duration::from_millis(20));
+        assert_close(time.alpha(), 0.5);
+        assert_eq!(time.frame_time(), Duration::from_millis(15));
+
+        assert_eq!(frame(&mut time, Duration::from_millis(4)), 0);
+        assert_close(time.alpha(), 0.9);
+
+        assert_eq!(frame(&mut time, Duration::from_millis(1)), 1);
+        assert_eq!(time.elapsed(), Duration::from_millis(30));
+        assert_close(time.alpha(), 0.);
+    }
+
+    #[test]
+    fn paused_time_only_steps_on_request() {
+        let mut time = Time::new(STEP);
+        frame(&mut time, Duration::from_millis(5));
+        time.set_paused(true);
+        assert_eq!(frame(&mut time, Duration::from_millis(100)), 0);
+        assert_eq!(time.delta(), Duration::from_millis(100));
+        assert_close(time.alpha(), 1.);
+
+        time.step();
+        assert_eq!(frame(&mut time, Duration::from_millis(1)), 1);
+        assert_eq!(frame(&mut time, Duration::from_millis(100)), 0);
+        assert_eq!(time.elapsed(), STEP);
+
+        // Neither the paused frames nor a step requested while running carry over.
+        time.set_paused(false);
+        time.step();
+        assert_eq!(frame(&mut time, Duration::from_millis(4)), 0);
+        assert_close(time.alpha(), 0.9);
+    }
+
+    #[test]
+    fn stalls_are_clamped() {
+        let mut time = Time::new(STEP);
+        assert_eq!(frame(&mut time, Duration::from_secs(5)), 25);
+        assert_eq!(time.delta(), Duration::from_secs(5));
+        assert_eq!(time.elapsed(), time.max_delta);
+        assert_close(time.alpha(), 0.);
+    }
+}